@@ -0,0 +1,155 @@
+use codecrafters_grep::Regex;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use std::ops::Range;
+use syn::{parse_macro_input, LitStr};
+
+/// Checks a pattern at compile time and evaluates to a `&'static Regex` built
+/// from it the first time the expression runs.
+///
+/// ```ignore
+/// let regex = regex_macro::regex!("\\d+ apples");
+/// assert!(regex.is_match("3 apples"));
+/// ```
+///
+/// Each problem in an invalid pattern is a compile error pointing at its part
+/// of the string literal, or at the whole literal where the compiler cannot
+/// point inside it.
+#[proc_macro]
+pub fn regex(input: TokenStream) -> TokenStream {
+    let lit = parse_macro_input!(input as LitStr);
+    expand(&lit).into()
+}
+
+fn expand(lit: &LitStr) -> TokenStream2 {
+    let expr = lit.value();
+
+    let errors = Regex::diagnose(&expr).into_iter().map(|err| {
+        let span = source_range(&lit.token().to_string(), err.span.start..err.span.end)
+            .and_then(|range| lit.token().subspan(range))
+            .unwrap_or_else(|| lit.span());
+
+        syn::Error::new(span, format!("invalid regex: {err}"))
+    });
+
+    if let Some(err) = errors.reduce(|mut all, err| {
+        all.combine(err);
+        all
+    }) {
+        let errors = err.to_compile_error();
+        return quote! {{ #errors }};
+    }
+
+    quote! {{
+        static REGEX: ::std::sync::LazyLock<::codecrafters_grep::Regex> =
+            ::std::sync::LazyLock::new(|| {
+                ::codecrafters_grep::Regex::new(#lit).expect("regex! checked the pattern")
+            });
+
+        &*REGEX
+    }}
+}
+
+// Maps a byte range of the value of a string literal to the range of the
+// literal as written in `token`, escapes and all.
+fn source_range(token: &str, range: Range<usize>) -> Option<Range<usize>> {
+    if let Some(raw) = token.strip_prefix('r') {
+        let start = raw.find('"')? + 2;
+        return Some(range.start + start..range.end + start);
+    }
+
+    // The offset in `token` of every byte of the value, and of its end.
+    let mut offsets = vec![];
+    let mut chars = token.char_indices().skip(1).peekable();
+
+    loop {
+        let (i, c) = chars.next()?;
+
+        let len = match c {
+            '"' => {
+                offsets.push(i);
+                break;
+            }
+            '\\' => match chars.next()?.1 {
+                'x' => {
+                    chars.nth(1)?;
+                    1
+                }
+                'n' | 'r' | 't' | '\\' | '0' | '\'' | '"' => 1,
+                'u' => {
+                    let hex: String = chars
+                        .by_ref()
+                        .skip(1)
+                        .map(|(_, c)| c)
+                        .take_while(|&c| c != '}')
+                        .collect();
+
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?.len_utf8()
+                }
+                // A line continuation leaves out the line end and the
+                // whitespace after it.
+                '\n' | '\r' => {
+                    while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                    0
+                }
+                _ => return None,
+            },
+            c => c.len_utf8(),
+        };
+
+        offsets.extend(std::iter::repeat(i).take(len));
+    }
+
+    Some(*offsets.get(range.start)?..*offsets.get(range.end)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proc_macro2::Span;
+
+    fn expand_str(expr: &str) -> String {
+        expand(&LitStr::new(expr, Span::call_site())).to_string()
+    }
+
+    #[test]
+    fn it_builds_valid_patterns() {
+        let tokens = expand_str("\\d+ (cat|dog)s?$");
+        assert!(tokens.contains("Regex :: new"));
+        assert!(!tokens.contains("compile_error"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_patterns() {
+        let tokens = expand_str("a|b");
+        assert!(tokens.contains("compile_error"));
+        assert!(tokens
+            .contains("invalid regex: alternation is only supported inside a group at byte 1"));
+    }
+
+    #[test]
+    fn it_rejects_duplicate_group_names() {
+        let tokens = expand_str("(?P<a>x)(?P<a>y)");
+        assert!(tokens.contains("invalid regex: duplicate group name at byte 12"));
+    }
+
+    #[test]
+    fn it_reports_every_problem() {
+        let tokens = expand_str("a)b(");
+        assert_eq!(tokens.matches("compile_error").count(), 2);
+        assert!(tokens.contains("invalid regex: unmatched closing parenthesis at byte 1"));
+        assert!(tokens.contains("invalid regex: unclosed group at byte 3"));
+    }
+
+    #[test]
+    fn it_maps_pattern_bytes_to_the_literal() {
+        assert_eq!(source_range(r#""\\d+|x""#, 3..4), Some(5..6));
+        assert_eq!(source_range(r#""\\d+|x""#, 0..5), Some(1..7));
+        assert_eq!(source_range(r##"r#"\d+|x"#"##, 3..4), Some(6..7));
+        assert_eq!(source_range(r#""\u{e9}|""#, 2..3), Some(7..8));
+        assert_eq!(source_range(r#""\x41\t|""#, 2..3), Some(7..8));
+        assert_eq!(source_range("\"a\\\n   |\"", 1..2), Some(7..8));
+        assert_eq!(source_range(r#""ab""#, 3..4), None);
+    }
+}
@@ -0,0 +1,17 @@
+use regex_macro::regex;
+
+#[test]
+fn it_matches_with_checked_patterns() {
+    assert!(regex!("\\d+ apples").is_match("3 apples"));
+    assert!(!regex!("^(cat|dog)s?$").is_match("cats and dogs"));
+}
+
+#[test]
+fn it_builds_each_regex_once() {
+    fn apples() -> &'static codecrafters_grep::Regex {
+        regex!("\\d+ apples")
+    }
+
+    let other = std::thread::spawn(|| apples() as *const _ as usize);
+    assert_eq!(other.join().unwrap(), apples() as *const _ as usize);
+}
@@ -5,5 +5,17 @@ use clap::Parser;
 pub struct Args {
     #[arg(short = 'E')]
     pub extend: bool,
-    pub pattern: String,
+    /// Print only the matched parts of matching lines.
+    #[arg(short = 'o')]
+    pub only_matching: bool,
+    /// A pattern to search for. May be given several times, in which case
+    /// lines matching any of them are selected.
+    #[arg(short = 'e', value_name = "PATTERN")]
+    pub patterns: Vec<String>,
+    /// Search line-aligned chunks of the input on this many threads. The
+    /// output is the same as with a single thread.
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    pub threads: usize,
+    #[arg(required_unless_present = "patterns")]
+    pub pattern: Option<String>,
 }
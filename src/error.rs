@@ -5,6 +5,21 @@ pub enum Error {
     #[error("io -- {0}")]
     Io(#[from] std::io::Error),
 
-    #[error("other -- {0}")]
+    #[error("parse -- {0}")]
+    Parse(#[from] crate::re::ParseError),
+
+    #[error("dfa -- exceeds the size limit of {0} bytes")]
+    DfaTooLarge(usize),
+
+    #[error("dfa -- invalid serialized DFA: {0}")]
+    InvalidDfa(&'static str),
+
+    #[error("match -- exceeded the step limit of {0}")]
+    StepLimit(u64),
+
+    #[error("match -- timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
+    #[error("{0}")]
     Other(#[from] anyhow::Error),
 }
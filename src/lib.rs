@@ -1,25 +1,326 @@
 mod args;
 mod error;
+mod parallel;
 mod re;
 
-use re::Regex;
-use std::io::BufRead;
+use std::io::{BufRead, Write};
 
 pub use args::Args;
 pub use error::Error;
+pub use parallel::{par_match_pattern, par_match_patterns, par_print_matches};
+pub use re::{
+    bytes, CaptureMatches, Captures, DenseDfa, Diagnostic, FindMatches, Match, MatchKind,
+    ParseError, ParseErrorKind, Regex, RegexBuilder, RegexSet, RegexSetBuilder, Replacer, Span,
+    Split, SplitN, Stream, StreamMatch, TryFindMatches,
+};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub fn match_pattern<R: BufRead>(buf: R, pattern: &str) -> Result<bool> {
-    let regex = Regex::new(pattern);
+// Lines up to this long are read whole and searched with the regex. The rest
+// of a longer one is fed to a `Stream` as it is read instead.
+const LONG_LINE: usize = 1 << 20;
 
-    for line in buf.lines() {
-        let line = line?;
+/// Returns whether any line of `buf` matches `pattern`. A line longer than a
+/// megabyte is searched as a stream, so it never has to fit in memory.
+pub fn match_pattern<R: BufRead>(mut buf: R, pattern: &str) -> Result<bool> {
+    let regex = bytes::Regex::new(pattern)?;
+    let mut line = vec![];
 
-        if regex.is_match(&line) {
+    loop {
+        let available = buf.fill_buf()?;
+
+        if available.is_empty() {
+            return Ok(!line.is_empty() && regex.try_is_match(trim_newline(&line))?);
+        }
+
+        match available.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                line.extend_from_slice(&available[..=i]);
+                buf.consume(i + 1);
+
+                if regex.try_is_match(trim_newline(&line))? {
+                    return Ok(true);
+                }
+
+                line.clear();
+            }
+            None => {
+                let len = available.len();
+                line.extend_from_slice(available);
+                buf.consume(len);
+
+                if line.len() > LONG_LINE {
+                    if stream_line(&mut buf, &regex, &line)? {
+                        return Ok(true);
+                    }
+
+                    line.clear();
+                }
+            }
+        }
+    }
+}
+
+// Searches a line that starts with `head` and goes on in `buf`, reading it
+// up to and including its newline.
+fn stream_line<R: BufRead>(buf: &mut R, regex: &bytes::Regex, head: &[u8]) -> Result<bool> {
+    let mut stream = regex.stream();
+    let mut cr = false;
+
+    if push_line_part(&mut stream, &mut cr, head) {
+        return Ok(true);
+    }
+
+    loop {
+        let available = buf.fill_buf()?;
+
+        if available.is_empty() {
+            stream.finish();
+            return Ok(stream.is_match());
+        }
+
+        match available.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                let found = push_line_part(&mut stream, &mut cr, &available[..i]) || {
+                    stream.finish();
+                    stream.is_match()
+                };
+                buf.consume(i + 1);
+                return Ok(found);
+            }
+            None => {
+                let len = available.len();
+                let found = push_line_part(&mut stream, &mut cr, available);
+                buf.consume(len);
+
+                if found {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+// Pushes the next part of a line to `stream` and returns whether a match is
+// known to exist so far. A trailing `\r` is held back in `cr` until the next
+// part shows it does not end the line, like `trim_newline` drops it.
+fn push_line_part(stream: &mut Stream, cr: &mut bool, part: &[u8]) -> bool {
+    if part.is_empty() {
+        return false;
+    }
+
+    if std::mem::take(cr) {
+        stream.push(b"\r");
+    }
+
+    let part = match part.strip_suffix(b"\r") {
+        Some(part) => {
+            *cr = true;
+            part
+        }
+        None => part,
+    };
+
+    stream.push(part);
+    stream.is_match()
+}
+
+/// Like [`match_pattern`], but a line matches if any of `patterns` does. All
+/// of them are searched for in a single pass over each line.
+pub fn match_patterns<R: BufRead, S: AsRef<str>>(mut buf: R, patterns: &[S]) -> Result<bool> {
+    let set = bytes::RegexSet::new(patterns)?;
+    let mut line = vec![];
+
+    while buf.read_until(b'\n', &mut line)? > 0 {
+        if set.try_is_match(trim_newline(&line))? {
             return Ok(true);
         }
+
+        line.clear();
     }
 
     Ok(false)
 }
+
+/// Writes every non-empty match of any of `patterns` on its own line, like
+/// `grep -o`. Matches are leftmost-longest as in POSIX tools, as if the
+/// patterns were the branches of one alternation.
+pub fn print_matches<R: BufRead, W: Write, S: AsRef<str>>(
+    mut buf: R,
+    patterns: &[S],
+    mut out: W,
+) -> Result<bool> {
+    let regexes = only_matching_regexes(patterns)?;
+    let mut line = vec![];
+    let mut found = false;
+
+    while buf.read_until(b'\n', &mut line)? > 0 {
+        found |= print_line_matches(&regexes, trim_newline(&line), &mut out)?;
+        line.clear();
+    }
+
+    Ok(found)
+}
+
+fn only_matching_regexes<S: AsRef<str>>(patterns: &[S]) -> Result<Vec<bytes::Regex>> {
+    let mut regexes = vec![];
+
+    for pattern in patterns {
+        let regex = RegexBuilder::new(pattern.as_ref())
+            .match_kind(MatchKind::LeftmostLongest)
+            .build_bytes()?;
+        regexes.push(regex);
+    }
+
+    Ok(regexes)
+}
+
+fn print_line_matches<W: Write>(
+    regexes: &[bytes::Regex],
+    line: &[u8],
+    out: &mut W,
+) -> Result<bool> {
+    let mut found = false;
+
+    if let [regex] = regexes {
+        for m in regex.try_find_iter(line) {
+            found |= print_match(m?.text, out)?;
+        }
+
+        return Ok(found);
+    }
+
+    let mut at = 0;
+
+    while at <= line.len() {
+        // The match starting first wins, and the longest of those.
+        let mut best: Option<bytes::Match> = None;
+
+        for regex in regexes {
+            if let Some(m) = regex.try_find_at(line, at)? {
+                if best.map_or(true, |b| {
+                    m.start < b.start || m.start == b.start && m.end > b.end
+                }) {
+                    best = Some(m);
+                }
+            }
+        }
+
+        let Some(m) = best else {
+            break;
+        };
+
+        found |= print_match(m.text, out)?;
+
+        // No pattern matches anything but the empty string here, so the
+        // search goes on after the next char.
+        at = if m.start < m.end {
+            m.end
+        } else {
+            m.end + char_len(&line[m.end..])
+        };
+    }
+
+    Ok(found)
+}
+
+// Writes a non-empty match on its own line.
+fn print_match<W: Write>(text: &[u8], out: &mut W) -> Result<bool> {
+    if text.is_empty() {
+        return Ok(false);
+    }
+
+    out.write_all(text)?;
+    out.write_all(b"\n")?;
+    Ok(true)
+}
+
+// The length of the char `bytes` starts with, counting a byte that is not
+// part of a valid UTF-8 sequence as one.
+fn char_len(bytes: &[u8]) -> usize {
+    bytes
+        .utf8_chunks()
+        .next()
+        .and_then(|chunk| chunk.valid().chars().next())
+        .map_or(1, char::len_utf8)
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_searches_past_invalid_utf8_lines() {
+        let input: &[u8] = b"caf\xE9\r\nnothing\n3 apples\n";
+        assert!(match_pattern(input, "\\d apples$").unwrap());
+        assert!(!match_pattern(input, "pears").unwrap());
+    }
+
+    #[test]
+    fn it_streams_lines_too_long_to_hold() {
+        let search = |line: &[u8], pattern| {
+            let mut input = vec![b'x'; LONG_LINE + 10_000];
+            input.extend_from_slice(line);
+            input.extend_from_slice(b"\nnext\n");
+            match_pattern(std::io::BufReader::with_capacity(4096, &input[..]), pattern).unwrap()
+        };
+
+        assert!(search(b"42 apples\r", "x\\d+ apples$"));
+        assert!(search(b"\r\r", "x\r$"));
+        assert!(!search(b"\r", "x\r"));
+        assert!(!search(b"apple", "apples"));
+        assert!(search(b"apple", "^next$"));
+        assert!(!search(b"", "^x$"));
+    }
+
+    #[test]
+    fn it_prints_only_the_matches() {
+        let input: &[u8] = b"ab abc\nnone\nabcab\n";
+        let mut out = vec![];
+
+        assert!(print_matches(input, &["(a|ab)c?"], &mut out).unwrap());
+        assert_eq!(out, b"ab\nabc\nabc\nab\n");
+        assert!(!print_matches(input, &["x*"], &mut vec![]).unwrap());
+    }
+
+    #[test]
+    fn it_prints_the_matches_of_several_patterns() {
+        let input: &[u8] = b"ab abcd\nnone\n12ab3\n\xE9x\n";
+        let mut out = vec![];
+
+        assert!(print_matches(input, &["ab", "abc\\w*", "\\d"], &mut out).unwrap());
+        assert_eq!(out, b"ab\nabcd\n1\n2\nab\n3\n");
+
+        let mut out = vec![];
+        assert!(print_matches(input, &["y*", "^n\\w", "x$"], &mut out).unwrap());
+        assert_eq!(out, b"no\nx\n");
+    }
+
+    #[test]
+    fn it_fails_printing_matches_over_the_step_limit() {
+        let regex = RegexBuilder::new("(a+)+\\d")
+            .match_kind(MatchKind::LeftmostLongest)
+            .step_limit(1000)
+            .build_bytes()
+            .unwrap();
+        let line = "a".repeat(10_000);
+        let result = print_line_matches(std::slice::from_ref(&regex), line.as_bytes(), &mut vec![]);
+        assert!(matches!(result, Err(Error::StepLimit(1000))));
+
+        let regexes = [regex, bytes::Regex::new("b").unwrap()];
+        let result = print_line_matches(&regexes, line.as_bytes(), &mut vec![]);
+        assert!(matches!(result, Err(Error::StepLimit(1000))));
+    }
+
+    #[test]
+    fn it_matches_any_of_several_patterns() {
+        let input: &[u8] = b"12 WARN cpu\n13 INFO disk\n";
+        assert!(match_patterns(input, &["ERROR", "disk$"]).unwrap());
+        assert!(!match_patterns(input, &["ERROR", "^WARN"]).unwrap());
+    }
+}
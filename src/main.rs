@@ -1,27 +1,66 @@
 use clap::Parser;
-use codecrafters_grep::{match_pattern, Args, Result};
+use codecrafters_grep::{
+    match_pattern, match_patterns, par_match_pattern, par_match_patterns, par_print_matches,
+    print_matches, Args, Regex, Result,
+};
 use std::io;
 use std::process;
 
 // Usage: echo <input_text> | your_program.sh -E <pattern>
+//
+// Exits with 0 if a line matched, 1 if none did and 2 on an error, like GNU
+// grep.
 fn main() {
     if let Err(err) = run() {
         eprintln!("{err}");
-        process::exit(1);
+        process::exit(2);
     }
 }
 
 fn run() -> Result<()> {
-    let Args { extend, pattern } = Args::parse();
+    let Args {
+        extend,
+        only_matching,
+        mut patterns,
+        threads,
+        pattern,
+    } = Args::parse();
 
     if !extend {
         eprintln!("Expected first argument to be '-E'");
-        process::exit(1);
+        process::exit(2);
     }
 
     let input = io::stdin().lock();
 
-    if match_pattern(input, &pattern)? {
+    patterns.extend(pattern);
+
+    // Point at what is wrong with every invalid pattern before reading input.
+    let mut invalid = false;
+
+    for pattern in &patterns {
+        for err in Regex::diagnose(pattern) {
+            eprint!("{}", err.diagnostic(pattern));
+            invalid = true;
+        }
+    }
+
+    if invalid {
+        process::exit(2);
+    }
+
+    let out = io::stdout().lock();
+
+    let found = match (patterns.as_slice(), threads) {
+        (patterns, 1) if only_matching => print_matches(input, patterns, out)?,
+        (patterns, _) if only_matching => par_print_matches(input, patterns, out, threads)?,
+        ([pattern], 1) => match_pattern(input, pattern)?,
+        ([pattern], _) => par_match_pattern(input, pattern, threads)?,
+        (patterns, 1) => match_patterns(input, patterns)?,
+        (patterns, _) => par_match_patterns(input, patterns, threads)?,
+    };
+
+    if found {
         process::exit(0)
     } else {
         process::exit(1)
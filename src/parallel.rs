@@ -0,0 +1,358 @@
+use crate::re::bytes;
+use crate::{
+    only_matching_regexes, print_line_matches, stream_line, trim_newline, Result, LONG_LINE,
+};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::thread;
+
+/// Chunks are cut at the first line end after this many bytes.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Like [`crate::match_pattern`], but searches line-aligned chunks of the
+/// input on `threads` threads at once.
+pub fn par_match_pattern<R: BufRead>(buf: R, pattern: &str, threads: usize) -> Result<bool> {
+    let regex = bytes::Regex::new(pattern)?;
+
+    search(
+        buf,
+        io::sink(),
+        threads,
+        CHUNK_SIZE,
+        true,
+        Some(&regex),
+        |chunk, _| {
+            for line in lines(chunk) {
+                if regex.try_is_match(line)? {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        },
+    )
+}
+
+/// Like [`crate::match_patterns`], but searches line-aligned chunks of the
+/// input on `threads` threads at once.
+pub fn par_match_patterns<R: BufRead, S: AsRef<str>>(
+    buf: R,
+    patterns: &[S],
+    threads: usize,
+) -> Result<bool> {
+    let set = bytes::RegexSet::new(patterns)?;
+
+    search(
+        buf,
+        io::sink(),
+        threads,
+        CHUNK_SIZE,
+        true,
+        None,
+        |chunk, _| {
+            for line in lines(chunk) {
+                if set.try_is_match(line)? {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        },
+    )
+}
+
+/// Like [`crate::print_matches`], but searches line-aligned chunks of the
+/// input on `threads` threads at once. The output is the same, in the same
+/// order.
+pub fn par_print_matches<R: BufRead, W: Write, S: AsRef<str>>(
+    buf: R,
+    patterns: &[S],
+    out: W,
+    threads: usize,
+) -> Result<bool> {
+    let regexes = only_matching_regexes(patterns)?;
+
+    search(buf, out, threads, CHUNK_SIZE, false, None, |chunk, out| {
+        print_chunk_matches(&regexes, chunk, out)
+    })
+}
+
+fn print_chunk_matches(regexes: &[bytes::Regex], chunk: &[u8], out: &mut Vec<u8>) -> Result<bool> {
+    let mut found = false;
+
+    for line in lines(chunk) {
+        found |= print_line_matches(regexes, line, out)?;
+    }
+
+    Ok(found)
+}
+
+// A chunk handed to a worker, numbered in input order.
+type Job = (usize, Vec<u8>);
+
+// What a worker printed for a chunk and whether it matched, or the panic it
+// ran into.
+type Done = (usize, thread::Result<Result<(bool, Vec<u8>)>>);
+
+// Starts `threads` workers that run `search_chunk` on the chunks the input is
+// cut into, and writes what they printed for each chunk to `out` in input
+// order. With `stop_early`, no more chunks are read once one was found to
+// match. With `stream`, a line longer than `LONG_LINE` is searched as a stream
+// as it is read, like in `match_pattern`, instead of being put in a chunk.
+fn search<R, W, F>(
+    mut buf: R,
+    mut out: W,
+    threads: usize,
+    chunk_size: usize,
+    stop_early: bool,
+    stream: Option<&bytes::Regex>,
+    search_chunk: F,
+) -> Result<bool>
+where
+    R: BufRead,
+    W: Write,
+    F: Fn(&[u8], &mut Vec<u8>) -> Result<bool> + Sync,
+{
+    let threads = threads.max(1);
+    let (job_tx, job_rx) = mpsc::channel::<Job>();
+    let (done_tx, done_rx) = mpsc::channel::<Done>();
+    let job_rx = Mutex::new(job_rx);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let done_tx = done_tx.clone();
+            let (job_rx, search_chunk) = (&job_rx, &search_chunk);
+
+            // A worker stops once the chunks run out or nobody waits for its
+            // results any more.
+            scope.spawn(move || loop {
+                let job = job_rx.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                let Ok((seq, chunk)) = job else {
+                    return;
+                };
+
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let mut printed = vec![];
+                    let matched = search_chunk(&chunk, &mut printed)?;
+                    Ok((matched, printed))
+                }));
+
+                if done_tx.send((seq, result)).is_err() {
+                    return;
+                }
+            });
+        }
+
+        drop(done_tx);
+
+        // Both ends are dropped however the search ends, which lets the
+        // workers stop before the scope waits for them.
+        let (jobs, done) = (job_tx, done_rx);
+        let max_line = stream.map(|_| LONG_LINE);
+        let mut results = BTreeMap::new();
+        let (mut sent, mut written) = (0, 0);
+        let mut found = false;
+        let mut eof = false;
+
+        loop {
+            // Keep at most two chunks per worker ahead of the output.
+            if !eof && sent - written < 2 * threads {
+                let Chunk { lines, long_line } = read_chunk(&mut buf, chunk_size, max_line)?;
+                eof = lines.is_empty() && long_line.is_none();
+
+                if !lines.is_empty() {
+                    // The receiving end lives as long as `search`, so this never fails.
+                    let _ = jobs.send((sent, lines));
+                    sent += 1;
+                }
+
+                if let (Some(head), Some(regex)) = (long_line, stream) {
+                    found |= stream_line(&mut buf, regex, &head)?;
+
+                    if found && stop_early {
+                        return Ok(true);
+                    }
+                }
+
+                continue;
+            }
+
+            if written == sent {
+                return Ok(found);
+            }
+
+            let (seq, result) = done.recv().expect("workers outlive their chunks");
+            results.insert(seq, result);
+
+            while let Some(result) = results.remove(&written) {
+                let (matched, printed) = result.unwrap_or_else(|err| panic::resume_unwind(err))?;
+                out.write_all(&printed)?;
+                found |= matched;
+                written += 1;
+            }
+
+            if found && stop_early {
+                return Ok(true);
+            }
+        }
+    })
+}
+
+// A piece of the input cut at line ends, see `read_chunk`.
+#[derive(Debug, PartialEq)]
+struct Chunk {
+    lines: Vec<u8>,
+    // The start of a line too long for a chunk, whose rest is still unread.
+    long_line: Option<Vec<u8>>,
+}
+
+// Reads at least `size` bytes, unless the input ends first, and then up to
+// the end of the line they stop in. With `max_line`, a last line longer than
+// that is left out of the chunk and only read up to there.
+fn read_chunk<R: BufRead>(buf: &mut R, size: usize, max_line: Option<usize>) -> Result<Chunk> {
+    let mut lines = Vec::with_capacity(size);
+    buf.take(size as u64).read_to_end(&mut lines)?;
+
+    if lines.last().map_or(true, |&b| b == b'\n') {
+        return Ok(Chunk {
+            lines,
+            long_line: None,
+        });
+    }
+
+    let Some(max_line) = max_line else {
+        buf.read_until(b'\n', &mut lines)?;
+
+        return Ok(Chunk {
+            lines,
+            long_line: None,
+        });
+    };
+
+    let line_start = lines.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let left = (max_line + 1).saturating_sub(lines.len() - line_start);
+    let read = buf.take(left as u64).read_until(b'\n', &mut lines)?;
+
+    let long_line =
+        (read == left && lines.last() != Some(&b'\n')).then(|| lines.split_off(line_start));
+
+    Ok(Chunk { lines, long_line })
+}
+
+fn lines(chunk: &[u8]) -> impl Iterator<Item = &[u8]> {
+    chunk.split_inclusive(|&b| b == b'\n').map(trim_newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::print_matches;
+
+    #[test]
+    fn it_cuts_chunks_at_line_ends() {
+        let mut input: &[u8] = b"abc\ndefgh\ni\n\nj";
+        let chunks: Vec<Vec<u8>> = std::iter::from_fn(|| {
+            Some(read_chunk(&mut input, 2, None).unwrap().lines).filter(|lines| !lines.is_empty())
+        })
+        .collect();
+
+        assert_eq!(chunks, [&b"abc\n"[..], b"defgh\n", b"i\n", b"\nj"]);
+    }
+
+    #[test]
+    fn it_leaves_long_lines_out_of_chunks() {
+        let mut input: &[u8] = b"ab\nlong line\nc";
+        let mut chunk = || read_chunk(&mut input, 2, Some(5)).unwrap();
+
+        assert_eq!(chunk().lines, b"ab\n");
+        assert_eq!(
+            chunk(),
+            Chunk {
+                lines: vec![],
+                long_line: Some(b"long l".to_vec()),
+            }
+        );
+        assert_eq!(input, b"ine\nc");
+    }
+
+    #[test]
+    fn it_prints_matches_in_input_order() {
+        let input: String = (0..500)
+            .map(|i| format!("line {i}: id{} x{}\n", i * 7, i % 3))
+            .collect();
+        let regexes = only_matching_regexes(&["id\\d+"]).unwrap();
+
+        let mut sequential = vec![];
+        print_matches(input.as_bytes(), &["id\\d+"], &mut sequential).unwrap();
+
+        for (threads, chunk_size) in [(1, 10), (3, 64), (8, 1000)] {
+            let mut parallel = vec![];
+            let found = search(
+                input.as_bytes(),
+                &mut parallel,
+                threads,
+                chunk_size,
+                false,
+                None,
+                |chunk, out| print_chunk_matches(&regexes, chunk, out),
+            )
+            .unwrap();
+
+            assert!(found);
+            assert_eq!(parallel, sequential, "{threads} threads");
+        }
+    }
+
+    #[test]
+    fn it_searches_lines_longer_than_a_chunk() {
+        let line = "id1 ".repeat(1000);
+        let input = format!("id0\n{line}\nid2\n");
+
+        let mut sequential = vec![];
+        print_matches(input.as_bytes(), &["id\\d"], &mut sequential).unwrap();
+
+        let mut parallel = vec![];
+        par_print_matches(input.as_bytes(), &["id\\d"], &mut parallel, 3).unwrap();
+        assert_eq!(parallel, sequential);
+
+        let search = |line: &[u8], pattern| {
+            let mut input = b"no\n".to_vec();
+            input.extend(std::iter::repeat(b'x').take(LONG_LINE + 10_000));
+            input.extend_from_slice(line);
+            input.extend_from_slice(b"\nnext\n");
+
+            let regex = bytes::Regex::new(pattern).unwrap();
+            let input = io::BufReader::with_capacity(4096, &input[..]);
+            search(
+                input,
+                io::sink(),
+                4,
+                4096,
+                true,
+                Some(&regex),
+                |chunk, _| Ok(lines(chunk).any(|line| regex.is_match(line))),
+            )
+            .unwrap()
+        };
+
+        assert!(search(b"42 apples\r", "x\\d+ apples$"));
+        assert!(!search(b"apple", "apples"));
+        assert!(search(b"apple", "^next$"));
+        assert!(!search(b"", "^x$"));
+    }
+
+    #[test]
+    fn it_matches_patterns_in_parallel() {
+        let input = "nothing here\n".repeat(10_000) + "3 apples\r\n";
+
+        assert!(par_match_pattern(input.as_bytes(), "\\d apples$", 4).unwrap());
+        assert!(!par_match_pattern(input.as_bytes(), "pears", 4).unwrap());
+        assert!(par_match_patterns(input.as_bytes(), &["pears", "^\\d"], 4).unwrap());
+
+        let mut out = vec![];
+        assert!(par_print_matches(input.as_bytes(), &["\\w+s$"], &mut out, 4).unwrap());
+        assert_eq!(out, b"apples\n");
+    }
+}
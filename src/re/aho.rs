@@ -0,0 +1,173 @@
+const ROOT: usize = 0;
+
+/// An Aho-Corasick automaton that finds any of several literals in one pass.
+///
+/// Transitions are resolved through the failure links up front, so the search
+/// follows exactly one transition per byte. Bytes that appear in no literal
+/// share a single class to keep the table small.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AhoCorasick {
+    literals: Vec<Vec<u8>>,
+    classes: [u16; 256],
+    stride: usize,
+    trans: Vec<usize>,
+    // Length of the longest literal prefix each state stands for.
+    depth: Vec<usize>,
+    // Whether some literal ends in each state.
+    output: Vec<bool>,
+}
+
+impl AhoCorasick {
+    pub fn new<I, L>(literals: I) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        L: AsRef<[u8]>,
+    {
+        let literals: Vec<Vec<u8>> = literals.into_iter().map(|l| l.as_ref().to_vec()).collect();
+
+        let mut classes = [0u16; 256];
+        let mut stride = 1;
+        for &b in literals.iter().flatten() {
+            if classes[b as usize] == 0 {
+                classes[b as usize] = stride as u16;
+                stride += 1;
+            }
+        }
+
+        let mut ac = Self {
+            literals,
+            classes,
+            stride,
+            trans: vec![],
+            depth: vec![],
+            output: vec![],
+        };
+        ac.build();
+        ac
+    }
+
+    fn add_state(&mut self, depth: usize) -> usize {
+        self.trans
+            .resize(self.trans.len() + self.stride, usize::MAX);
+        self.depth.push(depth);
+        self.output.push(false);
+        self.depth.len() - 1
+    }
+
+    fn build(&mut self) {
+        self.add_state(0);
+
+        for i in 0..self.literals.len() {
+            let mut cur = ROOT;
+
+            for j in 0..self.literals[i].len() {
+                let class = self.classes[self.literals[i][j] as usize] as usize;
+
+                cur = match self.trans[cur * self.stride + class] {
+                    usize::MAX => {
+                        let next = self.add_state(j + 1);
+                        self.trans[cur * self.stride + class] = next;
+                        next
+                    }
+                    next => next,
+                };
+            }
+
+            self.output[cur] = true;
+        }
+
+        // Breadth first, so that the failure target of every state is already
+        // complete when the state itself is visited.
+        let mut fail = vec![ROOT; self.depth.len()];
+        let mut queue = std::collections::VecDeque::new();
+
+        for class in 0..self.stride {
+            match self.trans[ROOT * self.stride + class] {
+                usize::MAX => self.trans[ROOT * self.stride + class] = ROOT,
+                next => queue.push_back(next),
+            }
+        }
+
+        while let Some(state) = queue.pop_front() {
+            self.output[state] |= self.output[fail[state]];
+
+            for class in 0..self.stride {
+                let via_fail = self.trans[fail[state] * self.stride + class];
+
+                match self.trans[state * self.stride + class] {
+                    usize::MAX => self.trans[state * self.stride + class] = via_fail,
+                    next => {
+                        fail[next] = via_fail;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_match(&self, hay: &[u8]) -> bool {
+        self.find_candidate(hay, 0).is_some()
+    }
+
+    /// Returns whether `hay` starts with one of the literals.
+    pub fn is_prefix_of(&self, hay: &[u8]) -> bool {
+        self.literals.iter().any(|l| hay.starts_with(l))
+    }
+
+    /// Returns a position at or after `from` that no occurrence of any
+    /// literal starts before, or `None` if there is no occurrence at all.
+    pub fn find_candidate(&self, hay: &[u8], from: usize) -> Option<usize> {
+        if self.output[ROOT] {
+            return Some(from);
+        }
+
+        let mut state = ROOT;
+
+        for (i, &b) in hay.iter().enumerate().skip(from) {
+            state = self.trans[state * self.stride + self.classes[b as usize] as usize];
+
+            if self.output[state] {
+                // Any occurrence still in progress is a suffix of what has been
+                // read so far, so it is no longer than the current state.
+                return Some(i + 1 - self.depth[state]);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_any_literal() {
+        let ac = AhoCorasick::new(["GET", "POST", "DELETE"]);
+        assert!(ac.is_match(b"x POST /"));
+        assert!(ac.is_match(b"DELETE"));
+        assert!(!ac.is_match(b"PUT /"));
+        assert!(!ac.is_match(b""));
+    }
+
+    #[test]
+    fn it_finds_literals_through_failure_links() {
+        let ac = AhoCorasick::new(["he", "she", "hers"]);
+        assert_eq!(ac.find_candidate(b"ushers", 0), Some(1));
+        assert!(ac.is_match(b"ahhe"));
+    }
+
+    #[test]
+    fn it_never_skips_past_a_longer_occurrence() {
+        let ac = AhoCorasick::new(["abcd", "bc"]);
+        assert_eq!(ac.find_candidate(b"xabcd", 0), Some(1));
+        assert_eq!(ac.find_candidate(b"xabcd", 2), Some(2));
+    }
+
+    #[test]
+    fn it_checks_prefixes() {
+        let ac = AhoCorasick::new(["GET", "POST"]);
+        assert!(ac.is_prefix_of(b"POST /"));
+        assert!(!ac.is_prefix_of(b" POST /"));
+    }
+}
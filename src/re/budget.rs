@@ -0,0 +1,98 @@
+use crate::{Error, Result};
+use std::time::{Duration, Instant};
+
+/// How often, in steps, a search looks at the clock.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Upper bounds on the work a single search may do.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub step_limit: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+/// The work left for one search.
+///
+/// A step is one unit of matcher work, such as following a transition or
+/// advancing one NFA state by one unit of the haystack.
+#[derive(Debug)]
+pub struct Budget {
+    steps: u64,
+    limits: Limits,
+    deadline: Option<Instant>,
+    next_check: u64,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            steps: 0,
+            limits,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            next_check: 0,
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(Limits::default())
+    }
+
+    /// The steps spent so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    #[inline]
+    pub fn spend(&mut self, steps: u64) -> Result<()> {
+        self.steps += steps;
+
+        if let Some(limit) = self.limits.step_limit.filter(|&limit| self.steps > limit) {
+            return Err(Error::StepLimit(limit));
+        }
+
+        if self.steps >= self.next_check {
+            self.next_check = self.steps + CLOCK_INTERVAL;
+
+            if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+                if Instant::now() >= deadline {
+                    return Err(Error::Timeout(timeout));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_stops_after_the_step_limit() {
+        let mut budget = Budget::new(Limits {
+            step_limit: Some(10),
+            timeout: None,
+        });
+
+        assert!(budget.spend(6).is_ok());
+        assert!(budget.spend(4).is_ok());
+        assert!(matches!(budget.spend(1), Err(Error::StepLimit(10))));
+    }
+
+    #[test]
+    fn it_stops_after_the_timeout() {
+        let mut budget = Budget::new(Limits {
+            step_limit: None,
+            timeout: Some(Duration::ZERO),
+        });
+
+        assert!(matches!(budget.spend(1), Err(Error::Timeout(_))));
+    }
+
+    #[test]
+    fn it_never_stops_without_limits() {
+        let mut budget = Budget::unlimited();
+        assert!(budget.spend(u32::MAX as u64).is_ok());
+    }
+}
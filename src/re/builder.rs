@@ -0,0 +1,197 @@
+use super::budget::Limits;
+use super::{bytes, MatchKind, ParseError, Regex, RegexSet};
+use std::time::Duration;
+
+/// Configures how a [`Regex`] is built and how much work its searches may do.
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    expr: String,
+    limits: Limits,
+    kind: MatchKind,
+}
+
+impl RegexBuilder {
+    pub fn new(expr: &str) -> Self {
+        Self {
+            expr: expr.to_string(),
+            limits: Limits::default(),
+            kind: MatchKind::default(),
+        }
+    }
+
+    /// Chooses which match searches report, see [`MatchKind`].
+    pub fn match_kind(&mut self, kind: MatchKind) -> &mut Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Fails a search with [`crate::Error::StepLimit`] once it has done more
+    /// than `steps` units of matcher work.
+    pub fn step_limit(&mut self, steps: u64) -> &mut Self {
+        self.limits.step_limit = Some(steps);
+        self
+    }
+
+    /// Fails a search with [`crate::Error::Timeout`] once it has run longer
+    /// than `timeout`.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Fails if the expression is not a valid pattern, see [`Regex::new`].
+    pub fn build(&self) -> Result<Regex, ParseError> {
+        Regex::with_options(&self.expr, self.limits, self.kind)
+    }
+
+    pub fn build_bytes(&self) -> Result<bytes::Regex, ParseError> {
+        self.build().map(bytes::Regex::from)
+    }
+}
+
+/// Configures how much work the searches of a [`RegexSet`] may do, see
+/// [`RegexBuilder`].
+#[derive(Debug, Clone)]
+pub struct RegexSetBuilder {
+    exprs: Vec<String>,
+    limits: Limits,
+}
+
+impl RegexSetBuilder {
+    pub fn new<I, S>(exprs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            exprs: exprs
+                .into_iter()
+                .map(|expr| expr.as_ref().to_string())
+                .collect(),
+            limits: Limits::default(),
+        }
+    }
+
+    /// See [`RegexBuilder::step_limit`].
+    pub fn step_limit(&mut self, steps: u64) -> &mut Self {
+        self.limits.step_limit = Some(steps);
+        self
+    }
+
+    /// See [`RegexBuilder::timeout`].
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Fails if any expression is not a valid pattern, see [`RegexSet::new`].
+    pub fn build(&self) -> Result<RegexSet, ParseError> {
+        RegexSet::with_limits(&self.exprs, self.limits)
+    }
+
+    pub fn build_bytes(&self) -> Result<bytes::RegexSet, ParseError> {
+        self.build().map(bytes::RegexSet::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn it_fails_searches_over_the_step_limit() {
+        let r = RegexBuilder::new("(a+)+\\d")
+            .step_limit(1000)
+            .build()
+            .unwrap();
+        let hay = "a".repeat(10_000);
+
+        assert!(matches!(r.try_is_match(&hay), Err(Error::StepLimit(1000))));
+        assert!(!r.is_match(&hay));
+        assert!(r.try_is_match("aaa1").unwrap());
+    }
+
+    #[test]
+    fn it_reports_iterations_over_the_step_limit() {
+        let r = RegexBuilder::new("(a+)+\\d")
+            .step_limit(1000)
+            .build()
+            .unwrap();
+        let hay = format!("a1 {}", "a".repeat(10_000));
+        let mut matches = r.try_find_iter(&hay);
+
+        assert_eq!(matches.next().unwrap().unwrap().text, "a1");
+        assert!(matches!(matches.next(), Some(Err(Error::StepLimit(1000)))));
+        assert!(matches.next().is_none());
+        assert_eq!(r.find_iter(&hay).count(), 1);
+    }
+
+    #[test]
+    fn it_fails_set_searches_over_the_step_limit() {
+        let set = RegexSetBuilder::new(["(a+)+\\d", "^(a|b)+\\d$"])
+            .step_limit(1000)
+            .build()
+            .unwrap();
+        let hay = "a".repeat(10_000);
+
+        assert!(matches!(
+            set.try_is_match(&hay),
+            Err(Error::StepLimit(1000))
+        ));
+        assert!(matches!(set.try_matches(&hay), Err(Error::StepLimit(1000))));
+        assert!(!set.is_match(&hay));
+        assert_eq!(set.matches("aa1"), [0, 1]);
+
+        let set = RegexSetBuilder::new(["a+b", "c+d"])
+            .step_limit(1000)
+            .build_bytes()
+            .unwrap();
+        assert!(matches!(
+            set.try_is_match(hay.as_bytes()),
+            Err(Error::StepLimit(1000))
+        ));
+        assert!(!set.try_is_match(b"no letters to match").unwrap());
+    }
+
+    #[test]
+    fn it_fails_searches_over_the_timeout() {
+        let r = RegexBuilder::new("(a|b)*c")
+            .timeout(Duration::ZERO)
+            .build_bytes()
+            .unwrap();
+
+        assert!(matches!(r.try_is_match(b"ababc"), Err(Error::Timeout(_))));
+    }
+
+    #[test]
+    fn it_reports_the_chosen_kind_of_match() {
+        let r = RegexBuilder::new("(a|ab)c?").build().unwrap();
+        assert_eq!(r.find("xabc").map(|m| m.text), Some("a"));
+
+        let r = RegexBuilder::new("(a|ab)c?")
+            .match_kind(MatchKind::LeftmostLongest)
+            .build()
+            .unwrap();
+        assert_eq!(r.find("xabc").map(|m| m.text), Some("abc"));
+    }
+
+    #[test]
+    fn it_searches_without_limits_by_default() {
+        let r = RegexBuilder::new("(a+)+$").build().unwrap();
+        let hay = format!("{}!", "a".repeat(10_000));
+
+        assert!(!r.try_is_match(&hay).unwrap());
+    }
+
+    #[test]
+    fn it_only_searches_around_inner_literals() {
+        let r = RegexBuilder::new("\\d+ ERROR \\w+")
+            .step_limit(1000)
+            .build()
+            .unwrap();
+        let hay = format!("{} 12 ERROR disk", "x".repeat(100_000));
+
+        assert!(r.try_is_match(&hay).unwrap());
+    }
+}
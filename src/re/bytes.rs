@@ -0,0 +1,245 @@
+use super::{ParseError, Spans, Stream};
+use crate::Result;
+use std::fmt;
+
+/// A match of a regex in a byte haystack, see [`super::Match`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    pub start: usize,
+    pub end: usize,
+    pub text: &'h [u8],
+}
+
+/// A regex that matches byte slices which are not necessarily valid UTF-8.
+///
+/// Valid UTF-8 sequences are matched as chars exactly like [`super::Regex`]
+/// does. A byte that is not part of one is never matched by `.` or a negated
+/// group, only by a `\xHH` escape of the same byte. Such an escape matches the
+/// char U+00HH in valid UTF-8 too.
+#[derive(Debug, Clone)]
+pub struct Regex(super::Regex);
+
+impl Regex {
+    pub fn new(expr: &str) -> std::result::Result<Self, ParseError> {
+        super::Regex::new(expr).map(Self)
+    }
+
+    /// A search that runs out of its limits counts as no match, see
+    /// [`super::Regex::is_match`].
+    pub fn is_match(&self, hay: &[u8]) -> bool {
+        self.try_is_match(hay).unwrap_or(false)
+    }
+
+    pub fn try_is_match(&self, hay: &[u8]) -> Result<bool> {
+        self.0.try_is_match_bytes(hay)
+    }
+
+    pub fn find<'h>(&self, hay: &'h [u8]) -> Option<Match<'h>> {
+        self.try_find(hay).unwrap_or(None)
+    }
+
+    pub fn try_find<'h>(&self, hay: &'h [u8]) -> Result<Option<Match<'h>>> {
+        let span = self.0.try_find_at_bytes(hay, 0, &mut [])?;
+
+        Ok(span.map(|(start, end)| Match {
+            start,
+            end,
+            text: &hay[start..end],
+        }))
+    }
+
+    /// See [`super::Regex::stream`].
+    pub fn stream(&self) -> Stream<'_> {
+        self.0.stream()
+    }
+
+    /// See [`super::Regex::find_iter`]. An empty match right before an invalid
+    /// byte resumes the search after that byte.
+    pub fn find_iter<'r, 'h>(&'r self, hay: &'h [u8]) -> FindMatches<'r, 'h> {
+        FindMatches {
+            spans: Spans::new(&self.0, hay),
+            hay,
+        }
+    }
+
+    /// Like [`Regex::try_find`], but only for matches starting at or after
+    /// `at`. A `^` still only matches at the start of `hay`.
+    pub fn try_find_at<'h>(&self, hay: &'h [u8], at: usize) -> Result<Option<Match<'h>>> {
+        let span = self.0.try_find_at_bytes(hay, at, &mut [])?;
+
+        Ok(span.map(|(start, end)| Match {
+            start,
+            end,
+            text: &hay[start..end],
+        }))
+    }
+
+    /// See [`super::Regex::try_find_iter`].
+    pub fn try_find_iter<'r, 'h>(&'r self, hay: &'h [u8]) -> TryFindMatches<'r, 'h> {
+        TryFindMatches {
+            spans: Spans::new(&self.0, hay),
+            hay,
+        }
+    }
+}
+
+/// An iterator over the matches of a [`Regex`], see [`Regex::find_iter`].
+#[derive(Debug)]
+pub struct FindMatches<'r, 'h> {
+    spans: Spans<'r, 'h>,
+    hay: &'h [u8],
+}
+
+impl<'h> Iterator for FindMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.spans.next()?.ok()?;
+
+        Some(Match {
+            start,
+            end,
+            text: &self.hay[start..end],
+        })
+    }
+}
+
+/// An iterator over the matches of a [`Regex`] that reports searches which
+/// run out of their limits, see [`Regex::try_find_iter`].
+#[derive(Debug)]
+pub struct TryFindMatches<'r, 'h> {
+    spans: Spans<'r, 'h>,
+    hay: &'h [u8],
+}
+
+impl<'h> Iterator for TryFindMatches<'_, 'h> {
+    type Item = Result<Match<'h>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let span = self.spans.next()?;
+
+        Some(span.map(|(start, end)| Match {
+            start,
+            end,
+            text: &self.hay[start..end],
+        }))
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<super::Regex> for Regex {
+    fn from(regex: super::Regex) -> Self {
+        Self(regex)
+    }
+}
+
+/// A set of regexes that matches byte slices, see [`super::RegexSet`] and
+/// [`Regex`].
+#[derive(Debug, Clone)]
+pub struct RegexSet(super::RegexSet);
+
+impl RegexSet {
+    pub fn new<I, S>(exprs: I) -> std::result::Result<Self, ParseError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        super::RegexSet::new(exprs).map(Self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_match(&self, hay: &[u8]) -> bool {
+        self.try_is_match(hay).unwrap_or(false)
+    }
+
+    pub fn try_is_match(&self, hay: &[u8]) -> Result<bool> {
+        self.0.try_is_match_bytes(hay)
+    }
+
+    pub fn matches(&self, hay: &[u8]) -> Vec<usize> {
+        self.try_matches(hay).unwrap_or_default()
+    }
+
+    pub fn try_matches(&self, hay: &[u8]) -> Result<Vec<usize>> {
+        self.0.try_matches_bytes(hay)
+    }
+}
+
+impl From<super::RegexSet> for RegexSet {
+    fn from(set: super::RegexSet) -> Self {
+        Self(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_valid_utf8_like_str() {
+        let r = Regex::new("g.+gol").unwrap();
+        assert!(r.is_match("goøö0Ogol".as_bytes()));
+        assert!(!r.is_match(b"gol"));
+    }
+
+    #[test]
+    fn it_does_not_match_invalid_bytes_with_wildcards() {
+        let r = Regex::new("a.b").unwrap();
+        assert!(r.is_match(b"a\xC3\xA9b"));
+        assert!(!r.is_match(b"a\xFFb"));
+
+        let r = Regex::new("a[^x]b").unwrap();
+        assert!(!r.is_match(b"a\xFFb"));
+    }
+
+    #[test]
+    fn it_matches_invalid_bytes_with_escapes() {
+        let r = Regex::new("a\\xFFb").unwrap();
+        assert!(r.is_match(b"xa\xFFb"));
+        assert!(!r.is_match(b"ayb"));
+
+        let r = Regex::new("\\xFF+$").unwrap();
+        assert!(r.is_match(b"ok \xFF\xFF"));
+        assert!(!r.is_match(b"ok \xFF\xFFa"));
+        let r = Regex::new("caf\\xE9$").unwrap();
+        assert!(r.is_match(b"caf\xE9"));
+        assert!(r.is_match("café".as_bytes()));
+        assert!(!r.is_match(b"caf\xC3"));
+    }
+
+    #[test]
+    fn it_keeps_matching_after_invalid_bytes() {
+        let r = Regex::new("\\d+ apples").unwrap();
+        assert!(r.is_match(b"\xFF\xFE 3 apples"));
+    }
+
+    #[test]
+    fn it_finds_matches_around_invalid_bytes() {
+        let r = Regex::new("\\d+").unwrap();
+        let texts: Vec<&[u8]> = r.find_iter(b"1\xFF22\xFE").map(|m| m.text).collect();
+        assert_eq!(texts, [&b"1"[..], b"22"]);
+
+        let r = Regex::new("a*").unwrap();
+        let spans: Vec<(usize, usize)> = r.find_iter(b"\xFFa").map(|m| (m.start, m.end)).collect();
+        assert_eq!(spans, [(0, 0), (1, 2)]);
+    }
+
+    #[test]
+    fn it_matches_sets_around_invalid_bytes() {
+        let set = RegexSet::new(["\\xFF$", "^a.b", "\\d"]).unwrap();
+        assert_eq!(set.matches(b"a\xC3\xA9b \xFF"), [0, 1]);
+        assert!(!set.is_match(b"a\xFFb"));
+    }
+}
@@ -0,0 +1,192 @@
+use super::{Match, Regex, Spans};
+use std::sync::Arc;
+
+/// The spans matched by the capture groups of a regex in one match.
+#[derive(Debug, Clone)]
+pub struct Captures<'h> {
+    hay: &'h str,
+    // The start and end of every group, the whole match being group 0.
+    slots: Vec<Option<usize>>,
+    names: Arc<[Option<String>]>,
+}
+
+impl<'h> Captures<'h> {
+    /// Returns the match of group `i`, or `None` if the group did not take
+    /// part in the match. Group 0 is the whole match.
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        let start = (*self.slots.get(2 * i)?)?;
+        let end = (*self.slots.get(2 * i + 1)?)?;
+
+        Some(Match {
+            start,
+            end,
+            text: &self.hay[start..end],
+        })
+    }
+
+    /// Returns the match of the group called `name`.
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let i = self
+            .names
+            .iter()
+            .position(|group| group.as_deref() == Some(name))?;
+        self.get(i)
+    }
+
+    /// Appends `template` to `dst` with every group reference replaced by the
+    /// text of that group.
+    ///
+    /// `$1` and `${1}` refer to a group by number, `$name` and `${name}` by
+    /// name, and `$$` is a literal `$`. A reference takes the longest run of
+    /// letters, digits and underscores, so `$1a` refers to a group called
+    /// `1a` and `${1}a` must be written instead. Groups that do not exist or
+    /// did not match are replaced by nothing.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(tail) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = tail;
+                continue;
+            }
+
+            let (name, tail) = match rest.strip_prefix('{') {
+                Some(braced) => braced.split_once('}').unwrap_or(("", rest)),
+                None => {
+                    let end = rest
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+
+            if name.is_empty() {
+                dst.push('$');
+                continue;
+            }
+
+            let group = match name.parse() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(name),
+            };
+
+            if let Some(m) = group {
+                dst.push_str(m.text);
+            }
+
+            rest = tail;
+        }
+
+        dst.push_str(rest);
+    }
+}
+
+impl Regex {
+    /// The number of capture groups, including group 0 for the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns the capture groups of the leftmost match in `s`.
+    pub fn captures<'h>(&self, s: &'h str) -> Option<Captures<'h>> {
+        self.captures_iter(s).next()
+    }
+
+    /// Returns the capture groups of every match [`Regex::find_iter`] finds.
+    pub fn captures_iter<'r, 'h>(&'r self, s: &'h str) -> CaptureMatches<'r, 'h> {
+        CaptureMatches {
+            spans: Spans::with_captures(self, s.as_bytes()),
+            hay: s,
+        }
+    }
+}
+
+/// An iterator over the captures of the matches of a [`Regex`], see
+/// [`Regex::captures_iter`].
+#[derive(Debug)]
+pub struct CaptureMatches<'r, 'h> {
+    spans: Spans<'r, 'h>,
+    hay: &'h str,
+}
+
+impl<'h> Iterator for CaptureMatches<'_, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.spans.next()?.ok()?;
+        let mut slots = vec![Some(start), Some(end)];
+        slots.extend_from_slice(&self.spans.slots);
+
+        Some(Captures {
+            hay: self.hay,
+            slots,
+            names: self.spans.regex.names.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseErrorKind;
+
+    #[test]
+    fn it_captures_groups() {
+        let r = Regex::new("(\\d+)-(?P<month>\\d+)(-(\\d+))?").unwrap();
+        assert_eq!(r.captures_len(), 5);
+
+        let caps = r.captures("on 2024-05!").unwrap();
+        assert_eq!(caps.get(0).unwrap().text, "2024-05");
+        assert_eq!(caps.get(1).unwrap().text, "2024");
+        assert_eq!(caps.name("month").unwrap().text, "05");
+        assert_eq!(caps.get(2), caps.name("month"));
+        assert!(caps.get(3).is_none());
+        assert!(caps.get(5).is_none());
+        assert!(caps.name("day").is_none());
+    }
+
+    #[test]
+    fn it_skips_non_capturing_groups() {
+        let r = Regex::new("(?:a|b)+(c)").unwrap();
+        assert_eq!(r.captures_len(), 2);
+        assert_eq!(r.captures("xabc").unwrap().get(1).unwrap().text, "c");
+    }
+
+    #[test]
+    fn it_rejects_duplicate_group_names() {
+        let err = Regex::new("(?P<a>x)((?<a>y)|z)").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::DuplicateName);
+        assert_eq!((err.span.start, err.span.end), (12, 13));
+    }
+
+    #[test]
+    fn it_iterates_over_captures() {
+        let r = Regex::new("(\\w)=(\\d)").unwrap();
+        let pairs: Vec<(&str, &str)> = r
+            .captures_iter("a=1 b=2, c=x")
+            .map(|caps| (caps.get(1).unwrap().text, caps.get(2).unwrap().text))
+            .collect();
+        assert_eq!(pairs, [("a", "1"), ("b", "2")]);
+    }
+
+    #[test]
+    fn it_expands_templates() {
+        let r = Regex::new("(?<key>\\w+)=(\\d+)").unwrap();
+        let caps = r.captures("size=42").unwrap();
+        let expand = |template: &str| {
+            let mut dst = String::new();
+            caps.expand(template, &mut dst);
+            dst
+        };
+
+        assert_eq!(expand("$2 $key"), "42 size");
+        assert_eq!(expand("${2}0 ${key}s"), "420 sizes");
+        assert_eq!(expand("$$1 costs $"), "$1 costs $");
+        assert_eq!(expand("$2x $9 ${missing}"), "  ");
+        assert_eq!(expand("${unclosed"), "${unclosed");
+    }
+}
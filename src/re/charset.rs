@@ -0,0 +1,250 @@
+/// A unit of the haystack as seen by the automata.
+///
+/// Valid chars map to their scalar value, and a byte that is not part of a
+/// valid UTF-8 sequence maps to `RAW_BYTE` plus the byte, past every char.
+pub type Unit = u32;
+
+pub const MAX_CHAR: Unit = char::MAX as Unit;
+pub const RAW_BYTE: Unit = MAX_CHAR + 1;
+pub const MAX_UNIT: Unit = RAW_BYTE + 0xFF;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CharSet {
+    // Sorted, non-overlapping and non-adjacent inclusive ranges.
+    ranges: Vec<(Unit, Unit)>,
+}
+
+impl CharSet {
+    pub fn empty() -> Self {
+        Self { ranges: vec![] }
+    }
+
+    /// Every valid char, but no raw byte.
+    pub fn any() -> Self {
+        Self {
+            ranges: vec![(0, MAX_CHAR)],
+        }
+    }
+
+    pub fn single(c: char) -> Self {
+        Self {
+            ranges: vec![(c as Unit, c as Unit)],
+        }
+    }
+
+    /// The byte `b` where it is not part of a valid UTF-8 sequence, and the
+    /// char with the same code point, as in Latin-1. ASCII bytes are always
+    /// valid, so they are only the char itself.
+    pub fn byte(b: u8) -> Self {
+        if b.is_ascii() {
+            Self::single(b as char)
+        } else {
+            let raw = RAW_BYTE + b as Unit;
+            Self {
+                ranges: vec![(b as Unit, b as Unit), (raw, raw)],
+            }
+        }
+    }
+
+    pub fn digit() -> Self {
+        Self::from_chars([('0', '9')])
+    }
+
+    pub fn whitespace() -> Self {
+        Self::from_chars([('\t', '\r'), (' ', ' ')])
+    }
+
+    pub fn word() -> Self {
+        Self::from_chars([('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')])
+    }
+
+    pub fn from_chars<I: IntoIterator<Item = (char, char)>>(ranges: I) -> Self {
+        Self::from_ranges(ranges.into_iter().map(|(lo, hi)| (lo as Unit, hi as Unit)))
+    }
+
+    pub fn from_ranges<I: IntoIterator<Item = (Unit, Unit)>>(ranges: I) -> Self {
+        let mut ranges: Vec<(Unit, Unit)> = ranges.into_iter().collect();
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(Unit, Unit)> = Vec::with_capacity(ranges.len());
+
+        for (lo, hi) in ranges {
+            match merged.last_mut() {
+                Some(last) if lo <= last.1.saturating_add(1) => {
+                    last.1 = last.1.max(hi);
+                }
+                _ => merged.push((lo, hi)),
+            }
+        }
+
+        Self { ranges: merged }
+    }
+
+    pub fn ranges(&self) -> &[(Unit, Unit)] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, unit: Unit) -> bool {
+        self.ranges
+            .binary_search_by(|&(lo, hi)| {
+                if hi < unit {
+                    std::cmp::Ordering::Less
+                } else if lo > unit {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_ranges(self.ranges.iter().chain(other.ranges.iter()).copied())
+    }
+
+    /// Every valid char not in the set. Like `.`, a negated set never
+    /// matches raw bytes.
+    pub fn negate(&self) -> Self {
+        let mut ranges = vec![];
+        let mut lo = 0;
+
+        for &(start, end) in self.ranges.iter().filter(|&&(start, _)| start <= MAX_CHAR) {
+            if lo < start {
+                ranges.push((lo, start - 1));
+            }
+            lo = end + 1;
+        }
+
+        if lo <= MAX_CHAR {
+            ranges.push((lo, MAX_CHAR));
+        }
+
+        Self { ranges }
+    }
+}
+
+/// A partition of all units into classes that no `CharSet` of a pattern can
+/// tell apart. Automata use the class of a unit instead of the unit itself so
+/// that their transition tables stay small.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alphabet {
+    // The first unit of each class in ascending order. Always starts with 0.
+    boundaries: Vec<Unit>,
+    ascii: [u16; 128],
+}
+
+impl Alphabet {
+    pub fn new<'s, I: IntoIterator<Item = &'s CharSet>>(sets: I) -> Self {
+        let mut boundaries = vec![0];
+
+        for set in sets {
+            for &(lo, hi) in set.ranges() {
+                boundaries.push(lo);
+
+                if hi < MAX_UNIT {
+                    boundaries.push(hi + 1);
+                }
+            }
+        }
+
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        Self::from_boundaries(boundaries)
+    }
+
+    /// Builds an alphabet from the first unit of each class. `boundaries` must
+    /// be sorted, deduplicated and start with 0.
+    pub fn from_boundaries(boundaries: Vec<Unit>) -> Self {
+        let mut alphabet = Self {
+            boundaries,
+            ascii: [0; 128],
+        };
+
+        for b in 0..128 {
+            alphabet.ascii[b as usize] = alphabet.search_class(b) as u16;
+        }
+
+        alphabet
+    }
+
+    pub fn len(&self) -> usize {
+        self.boundaries.len()
+    }
+
+    pub fn boundaries(&self) -> &[Unit] {
+        &self.boundaries
+    }
+
+    pub fn class_of(&self, unit: Unit) -> usize {
+        if unit < 128 {
+            self.ascii[unit as usize] as usize
+        } else {
+            self.search_class(unit)
+        }
+    }
+
+    /// Returns a unit belonging to the given class.
+    pub fn representative(&self, class: usize) -> Unit {
+        self.boundaries[class]
+    }
+
+    fn search_class(&self, unit: Unit) -> usize {
+        self.boundaries.partition_point(|&b| b <= unit) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(c: char) -> Unit {
+        c as Unit
+    }
+
+    #[test]
+    fn it_merges_overlapping_ranges() {
+        let set = CharSet::from_chars([('a', 'c'), ('b', 'f'), ('g', 'h'), ('x', 'z')]);
+        assert_eq!(
+            set.ranges(),
+            &[(unit('a'), unit('h')), (unit('x'), unit('z'))]
+        );
+    }
+
+    #[test]
+    fn it_negates_sets() {
+        let set = CharSet::from_chars([('a', 'c'), ('x', 'z')]).negate();
+        assert!(set.contains(unit('\0')));
+        assert!(!set.contains(unit('b')));
+        assert!(set.contains(unit('d')));
+        assert!(!set.contains(unit('y')));
+        assert!(set.contains(unit('🗻')));
+
+        assert_eq!(CharSet::any().negate(), CharSet::empty());
+        assert_eq!(CharSet::empty().negate(), CharSet::any());
+    }
+
+    #[test]
+    fn it_keeps_raw_bytes_apart_from_chars() {
+        let raw = CharSet::byte(0xFF);
+        assert!(raw.contains(RAW_BYTE + 0xFF));
+        assert!(raw.contains('ÿ' as Unit));
+        assert!(!raw.contains(RAW_BYTE + 0xFE));
+        assert!(!CharSet::any().contains(RAW_BYTE + 0xFF));
+        assert!(!raw.negate().contains(RAW_BYTE + 0xFE));
+        assert_eq!(CharSet::byte(b'a'), CharSet::single('a'));
+    }
+
+    #[test]
+    fn it_partitions_units_into_classes() {
+        let digit = CharSet::digit();
+        let a = CharSet::single('a');
+        let alphabet = Alphabet::new([&digit, &a]);
+
+        assert_eq!(alphabet.len(), 5);
+        assert_eq!(alphabet.class_of(unit('0')), alphabet.class_of(unit('9')));
+        assert_ne!(alphabet.class_of(unit('0')), alphabet.class_of(unit('a')));
+        assert_eq!(alphabet.class_of(unit('b')), alphabet.class_of(unit('🗻')));
+        assert_ne!(alphabet.class_of(unit('/')), alphabet.class_of(unit('0')));
+    }
+}
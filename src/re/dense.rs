@@ -0,0 +1,431 @@
+use super::charset::{Alphabet, Unit, MAX_UNIT};
+use super::nfa::{Nfa, SparseSet, State, StateId};
+use super::utf8;
+use crate::{Error, Result};
+use std::collections::{HashMap, VecDeque};
+
+const MAGIC: &[u8; 8] = b"GREPDFA\0";
+const VERSION: u32 = 1;
+
+/// A fully compiled and minimized DFA.
+///
+/// Unlike the lazy DFA, every state is determinised up front, so a `DenseDfa`
+/// can be serialized with [`DenseDfa::to_bytes`] and loaded again with
+/// [`DenseDfa::from_bytes`] without parsing the pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DenseDfa {
+    alphabet: Alphabet,
+    start: usize,
+    accept: Vec<bool>,
+    trans: Vec<usize>,
+    // States that can never leave, indexed like `accept`.
+    absorbing: Vec<bool>,
+}
+
+impl DenseDfa {
+    /// Determinises `nfa`, failing once the transition table would take more
+    /// than `size_limit` bytes.
+    pub fn new(nfa: &Nfa, size_limit: usize) -> Result<Self> {
+        let stride = nfa.alphabet().len();
+        let mut builder = Builder {
+            nfa,
+            map: HashMap::new(),
+            sets: vec![],
+            stack: vec![],
+        };
+
+        let mut set = SparseSet::new(nfa.len());
+        nfa.add_closure(&mut set, nfa.start(), &mut builder.stack);
+        let start = builder.add(&set);
+
+        let mut trans: Vec<usize> = vec![];
+        let mut next = SparseSet::new(nfa.len());
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(id) = queue.pop_front() {
+            if (id + 1) * stride * 4 > size_limit {
+                return Err(Error::DfaTooLarge(size_limit));
+            }
+
+            trans.resize((id + 1) * stride, id);
+
+            if builder.is_sticky(id) {
+                // Every transition already loops back to the state itself.
+                continue;
+            }
+
+            for class in 0..stride {
+                let unit = nfa.alphabet().representative(class);
+                next.clear();
+
+                for &state in builder.sets[id].iter() {
+                    if let State::Char { set, next: to } = nfa.state(state) {
+                        if set.contains(unit) {
+                            nfa.add_closure(&mut next, *to, &mut builder.stack);
+                        }
+                    }
+                }
+
+                if !nfa.start_anchor() {
+                    nfa.add_closure(&mut next, nfa.start(), &mut builder.stack);
+                }
+
+                let len = builder.sets.len();
+                let to = builder.add(&next);
+
+                if to == len {
+                    queue.push_back(to);
+                }
+
+                trans[id * stride + class] = to;
+            }
+        }
+
+        let accept = (0..builder.sets.len())
+            .map(|id| builder.is_match(id))
+            .collect();
+
+        let dfa = Self::from_parts(nfa.alphabet().boundaries().to_vec(), start, accept, trans);
+
+        Ok(dfa.minimize())
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.accept.len()
+    }
+
+    pub fn is_match<H: AsRef<[u8]>>(&self, hay: H) -> bool {
+        let hay = hay.as_ref();
+        let stride = self.alphabet.len();
+        let mut cur = self.start;
+        let mut pos = 0;
+
+        while let Some((unit, len)) = utf8::decode(&hay[pos..]) {
+            if self.absorbing[cur] {
+                break;
+            }
+
+            cur = self.trans[cur * stride + self.alphabet.class_of(unit)];
+            pos += len;
+        }
+
+        self.accept[cur]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let mut push = |n: usize| bytes.extend((n as u32).to_le_bytes());
+
+        push(VERSION as usize);
+        push(self.alphabet.len());
+        self.alphabet
+            .boundaries()
+            .iter()
+            .for_each(|&c| push(c as usize));
+        push(self.num_states());
+        push(self.start);
+        self.accept.iter().for_each(|&a| push(a as usize));
+        self.trans.iter().for_each(|&t| push(t));
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or(Error::InvalidDfa("missing header"))?;
+
+        let mut words = rest.chunks(4).map(|chunk| {
+            chunk
+                .try_into()
+                .map(u32::from_le_bytes)
+                .map_err(|_| Error::InvalidDfa("truncated data"))
+        });
+        let mut next = move || {
+            words
+                .next()
+                .unwrap_or(Err(Error::InvalidDfa("truncated data")))
+        };
+
+        if next()? != VERSION {
+            return Err(Error::InvalidDfa("unsupported version"));
+        }
+
+        let classes = next()? as usize;
+        let boundaries = (0..classes)
+            .map(|_| {
+                next().and_then(|n| {
+                    (n <= MAX_UNIT)
+                        .then_some(n)
+                        .ok_or(Error::InvalidDfa("bad unit"))
+                })
+            })
+            .collect::<Result<Vec<Unit>>>()?;
+
+        if boundaries.first() != Some(&0) || boundaries.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::InvalidDfa("bad alphabet"));
+        }
+
+        let states = next()? as usize;
+        let start = next()? as usize;
+        let accept = (0..states)
+            .map(|_| next().map(|n| n != 0))
+            .collect::<Result<Vec<bool>>>()?;
+        let trans = (0..states * classes)
+            .map(|_| next().map(|n| n as usize))
+            .collect::<Result<Vec<usize>>>()?;
+
+        if next().is_ok() {
+            return Err(Error::InvalidDfa("trailing data"));
+        }
+
+        if start >= states || trans.iter().any(|&t| t >= states) {
+            return Err(Error::InvalidDfa("state out of range"));
+        }
+
+        Ok(Self::from_parts(boundaries, start, accept, trans))
+    }
+
+    fn from_parts(
+        boundaries: Vec<Unit>,
+        start: usize,
+        accept: Vec<bool>,
+        trans: Vec<usize>,
+    ) -> Self {
+        let alphabet = Alphabet::from_boundaries(boundaries);
+        let stride = alphabet.len();
+        let absorbing = (0..accept.len())
+            .map(|id| {
+                trans[id * stride..(id + 1) * stride]
+                    .iter()
+                    .all(|&t| t == id)
+            })
+            .collect();
+
+        Self {
+            alphabet,
+            start,
+            accept,
+            trans,
+            absorbing,
+        }
+    }
+
+    // Hopcroft's partition refinement. Every block of the final partition
+    // becomes one state of the minimized DFA.
+    fn minimize(self) -> Self {
+        let n = self.num_states();
+        let stride = self.alphabet.len();
+
+        // Predecessors of every state for every class.
+        let mut preds: Vec<Vec<usize>> = vec![vec![]; n * stride];
+        for from in 0..n {
+            for class in 0..stride {
+                preds[self.trans[from * stride + class] * stride + class].push(from);
+            }
+        }
+
+        let (accepting, rejecting): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&id| self.accept[id]);
+        let mut blocks: Vec<Vec<usize>> = vec![];
+        let mut block_of = vec![0; n];
+
+        for block in [accepting, rejecting] {
+            if !block.is_empty() {
+                block.iter().for_each(|&id| block_of[id] = blocks.len());
+                blocks.push(block);
+            }
+        }
+
+        let mut worklist: Vec<usize> = (0..blocks.len()).collect();
+        let mut in_worklist = vec![true; blocks.len()];
+        let mut marked = vec![false; n];
+
+        while let Some(splitter) = worklist.pop() {
+            in_worklist[splitter] = false;
+            let members = blocks[splitter].clone();
+
+            for class in 0..stride {
+                let mut touched: Vec<usize> = vec![];
+
+                for &to in members.iter() {
+                    for &from in preds[to * stride + class].iter() {
+                        if !marked[from] {
+                            marked[from] = true;
+                            touched.push(block_of[from]);
+                        }
+                    }
+                }
+
+                touched.sort_unstable();
+                touched.dedup();
+
+                for block in touched {
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        blocks[block].iter().partition(|&&id| marked[id]);
+
+                    if !outside.is_empty() {
+                        let new = blocks.len();
+                        inside.iter().for_each(|&id| block_of[id] = new);
+
+                        let smaller = if inside.len() <= outside.len() {
+                            new
+                        } else {
+                            block
+                        };
+                        blocks[block] = outside;
+                        blocks.push(inside);
+                        in_worklist.push(false);
+
+                        if in_worklist[block] {
+                            worklist.push(new);
+                            in_worklist[new] = true;
+                        } else {
+                            worklist.push(smaller);
+                            in_worklist[smaller] = true;
+                        }
+                    }
+                }
+
+                for &to in members.iter() {
+                    for &from in preds[to * stride + class].iter() {
+                        marked[from] = false;
+                    }
+                }
+            }
+        }
+
+        let mut trans = vec![0; blocks.len() * stride];
+        let mut accept = vec![false; blocks.len()];
+
+        for (id, block) in blocks.iter().enumerate() {
+            let rep = block[0];
+            accept[id] = self.accept[rep];
+
+            for class in 0..stride {
+                trans[id * stride + class] = block_of[self.trans[rep * stride + class]];
+            }
+        }
+
+        Self::from_parts(
+            self.alphabet.boundaries().to_vec(),
+            block_of[self.start],
+            accept,
+            trans,
+        )
+    }
+}
+
+struct Builder<'n> {
+    nfa: &'n Nfa,
+    map: HashMap<Vec<StateId>, usize>,
+    sets: Vec<Vec<StateId>>,
+    stack: Vec<StateId>,
+}
+
+impl Builder<'_> {
+    fn add(&mut self, set: &SparseSet) -> usize {
+        let mut key: Vec<StateId> = set
+            .iter()
+            .filter(|&id| !self.nfa.state(id).is_epsilon())
+            .collect();
+        key.sort_unstable();
+
+        // Without an end anchor a match is final, so every accepting set is
+        // collapsed into a single state that only contains the match.
+        if !self.nfa.end_anchor() && key.iter().any(|&id| self.is_match_state(id)) {
+            key.retain(|&id| self.is_match_state(id));
+        }
+
+        if let Some(&id) = self.map.get(&key) {
+            return id;
+        }
+
+        let id = self.sets.len();
+        self.sets.push(key.clone());
+        self.map.insert(key, id);
+        id
+    }
+
+    fn is_match_state(&self, id: StateId) -> bool {
+        matches!(self.nfa.state(id), State::Match(_))
+    }
+
+    fn is_match(&self, id: usize) -> bool {
+        self.sets[id].iter().any(|&s| self.is_match_state(s))
+    }
+
+    fn is_sticky(&self, id: usize) -> bool {
+        !self.nfa.end_anchor() && self.is_match(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::budget::Budget;
+    use super::super::hir::translate;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    const LIMIT: usize = 1 << 20;
+
+    fn dfa(expr: &str) -> DenseDfa {
+        let nfa = Nfa::new(&translate(&parse_pattern(expr).patterns()), false, false);
+        DenseDfa::new(&nfa, LIMIT).unwrap()
+    }
+
+    #[test]
+    fn it_matches_like_the_nfa() {
+        let expr = "\\d+ (apple|orange)s?";
+        let nfa = Nfa::new(&translate(&parse_pattern(expr).patterns()), false, true);
+        let dfa = DenseDfa::new(&nfa, LIMIT).unwrap();
+
+        for hay in ["3 apples", "12 orange", "3 apples!", "1 pear", "a 1 orange"] {
+            let expected = nfa.is_match(hay.as_bytes(), &mut Budget::unlimited());
+            assert_eq!(dfa.is_match(hay), expected.unwrap());
+        }
+    }
+
+    #[test]
+    fn it_minimizes_states() {
+        assert_eq!(dfa("(ab|ac)").num_states(), dfa("a[bc]").num_states());
+        assert_eq!(dfa("(a|aa|aaa)").num_states(), dfa("a").num_states());
+    }
+
+    #[test]
+    fn it_round_trips_through_bytes() {
+        let d = dfa("g.+gol");
+        let loaded = DenseDfa::from_bytes(&d.to_bytes()).unwrap();
+
+        assert_eq!(loaded, d);
+        assert!(loaded.is_match("goøö0Ogol"));
+        assert!(!loaded.is_match("gol"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_bytes() {
+        let mut bytes = dfa("abc").to_bytes();
+
+        assert!(DenseDfa::from_bytes(&bytes[1..]).is_err());
+        assert!(DenseDfa::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+
+        let last = bytes.len() - 4;
+        bytes[last..].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(DenseDfa::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn it_fails_beyond_the_size_limit() {
+        let nfa = Nfa::new(
+            &translate(&parse_pattern("a(a|b)(a|b)(a|b)(a|b)(a|b)c").patterns()),
+            false,
+            false,
+        );
+
+        assert!(matches!(
+            DenseDfa::new(&nfa, 256),
+            Err(Error::DfaTooLarge(256))
+        ));
+        assert!(DenseDfa::new(&nfa, LIMIT).is_ok());
+    }
+}
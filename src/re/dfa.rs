@@ -0,0 +1,314 @@
+use super::budget::Budget;
+use super::nfa::{Nfa, SparseSet, State, StateId};
+use super::prefilter::{LiteralKind, Prefilter};
+use crate::Result;
+use std::collections::HashMap;
+use std::mem;
+
+type DStateId = usize;
+
+const UNKNOWN: DStateId = DStateId::MAX;
+
+/// Default memory budget of a cache in bytes.
+const DEFAULT_CAPACITY: usize = 2 * (1 << 20);
+
+/// A search gives up on the DFA once the cache has been cleared this many
+/// times, since rebuilding states over and over is slower than simulating the
+/// NFA directly.
+const MAX_CLEARS: usize = 3;
+
+#[derive(Debug)]
+struct DState {
+    nfa_states: Box<[StateId]>,
+    is_match: bool,
+}
+
+/// DFA states built lazily from an NFA during searches.
+///
+/// Each DFA state is the set of NFA states reachable at some position, and a
+/// transition is only determinised the first time a search follows it. The
+/// cache is wiped whenever it grows beyond its capacity.
+#[derive(Debug)]
+pub struct Cache {
+    capacity: usize,
+    memory: usize,
+    clears: usize,
+    stride: usize,
+    states: Vec<DState>,
+    trans: Vec<DStateId>,
+    map: HashMap<Box<[StateId]>, DStateId>,
+    cur: SparseSet,
+    next: SparseSet,
+    stack: Vec<StateId>,
+}
+
+impl Cache {
+    pub fn new(nfa: &Nfa) -> Self {
+        Self::with_capacity(nfa, DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(nfa: &Nfa, capacity: usize) -> Self {
+        Self {
+            capacity,
+            memory: 0,
+            clears: 0,
+            stride: nfa.alphabet().len(),
+            states: vec![],
+            trans: vec![],
+            map: HashMap::new(),
+            cur: SparseSet::new(nfa.len()),
+            next: SparseSet::new(nfa.len()),
+            stack: vec![],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.trans.clear();
+        self.map.clear();
+        self.memory = 0;
+        self.clears += 1;
+    }
+
+    fn start(&mut self, nfa: &Nfa) -> DStateId {
+        let mut set = mem::replace(&mut self.cur, SparseSet::new(0));
+        set.clear();
+        nfa.add_closure(&mut set, nfa.start(), &mut self.stack);
+
+        let id = self.add(nfa, &set);
+        self.cur = set;
+        id
+    }
+
+    fn next_state(&mut self, nfa: &Nfa, from: DStateId, class: usize) -> DStateId {
+        let unit = nfa.alphabet().representative(class);
+        let mut next = mem::replace(&mut self.next, SparseSet::new(0));
+        next.clear();
+
+        for &id in self.states[from].nfa_states.iter() {
+            if let State::Char { set, next: to } = nfa.state(id) {
+                if set.contains(unit) {
+                    nfa.add_closure(&mut next, *to, &mut self.stack);
+                }
+            }
+        }
+
+        if !nfa.start_anchor() {
+            nfa.add_closure(&mut next, nfa.start(), &mut self.stack);
+        }
+
+        let id = self.add(nfa, &next);
+        self.next = next;
+        id
+    }
+
+    // Only states that consume a char or accept tell DFA states apart, so the
+    // key leaves out the splits visited while computing the closure.
+    fn add(&mut self, nfa: &Nfa, set: &SparseSet) -> DStateId {
+        let mut key: Vec<StateId> = set
+            .iter()
+            .filter(|&id| !nfa.state(id).is_epsilon())
+            .collect();
+        key.sort_unstable();
+
+        let is_match = key
+            .iter()
+            .any(|&id| matches!(nfa.state(id), State::Match(_)));
+        self.add_key(key.into_boxed_slice(), is_match)
+    }
+
+    fn add_key(&mut self, key: Box<[StateId]>, is_match: bool) -> DStateId {
+        if let Some(&id) = self.map.get(&key) {
+            return id;
+        }
+
+        let id = self.states.len();
+        self.memory += 2 * key.len() * mem::size_of::<StateId>()
+            + self.stride * mem::size_of::<DStateId>()
+            + mem::size_of::<DState>();
+
+        self.states.push(DState {
+            nfa_states: key.clone(),
+            is_match,
+        });
+        self.trans.resize(self.trans.len() + self.stride, UNKNOWN);
+        self.map.insert(key, id);
+        id
+    }
+}
+
+/// Runs the lazy DFA over `hay`. Returns `None` when the cache thrashes, in
+/// which case the caller should fall back to simulating the NFA.
+///
+/// Following a transition costs a step of `budget`, and building a new state
+/// costs a step for every NFA state in it.
+///
+/// With a prefix prefilter, the search jumps straight to the next occurrence
+/// of the prefix whenever no partial match is in progress.
+pub fn is_match(
+    nfa: &Nfa,
+    cache: &mut Cache,
+    hay: &[u8],
+    prefilter: Option<&Prefilter>,
+    budget: &mut Budget,
+) -> Result<Option<bool>> {
+    let end = find_end(nfa, cache, hay, 0, prefilter, budget)?;
+    Ok(end.map(|end| end.is_some()))
+}
+
+/// Like [`is_match`], but only looks for matches starting at or after `at`
+/// and returns where the one that ends first ends.
+pub fn find_end(
+    nfa: &Nfa,
+    cache: &mut Cache,
+    hay: &[u8],
+    at: usize,
+    prefilter: Option<&Prefilter>,
+    budget: &mut Budget,
+) -> Result<Option<Option<usize>>> {
+    cache.clears = 0;
+
+    if nfa.start_anchor() && at > 0 {
+        return Ok(Some(None));
+    }
+
+    let prefilter =
+        prefilter.filter(|pre| pre.kind() == LiteralKind::Prefix && !nfa.start_anchor());
+    let mut start = cache.start(nfa);
+    let mut cur = start;
+    let mut pos = at;
+
+    loop {
+        if cur == start {
+            if let Some(pre) = prefilter {
+                match pre.find(hay, pos) {
+                    Some(found) => pos = found,
+                    None => return Ok(Some(None)),
+                }
+            }
+        }
+
+        let state = &cache.states[cur];
+
+        if state.is_match && (!nfa.end_anchor() || pos == hay.len()) {
+            return Ok(Some(Some(pos)));
+        }
+
+        if state.nfa_states.is_empty() {
+            return Ok(Some(None));
+        }
+
+        let Some((unit, len)) = nfa.next_unit(hay, pos) else {
+            return Ok(Some(None));
+        };
+
+        budget.spend(1)?;
+
+        let class = nfa.alphabet().class_of(unit);
+        let mut next = cache.trans[cur * cache.stride + class];
+
+        if next == UNKNOWN {
+            if cache.memory > cache.capacity {
+                if cache.clears >= MAX_CLEARS {
+                    return Ok(None);
+                }
+
+                let nfa_states = cache.states[cur].nfa_states.clone();
+                let is_match = cache.states[cur].is_match;
+                cache.clear();
+                cur = cache.add_key(nfa_states, is_match);
+                start = cache.start(nfa);
+            }
+
+            budget.spend(cache.states[cur].nfa_states.len() as u64)?;
+            next = cache.next_state(nfa, cur, class);
+            cache.trans[cur * cache.stride + class] = next;
+        }
+
+        cur = next;
+        pos += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hir::translate;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    fn nfa(expr: &str) -> Nfa {
+        Nfa::new(&translate(&parse_pattern(expr).patterns()), false, false)
+    }
+
+    fn search(n: &Nfa, cache: &mut Cache, hay: &[u8], pre: Option<&Prefilter>) -> Option<bool> {
+        is_match(n, cache, hay, pre, &mut Budget::unlimited()).unwrap()
+    }
+
+    fn nfa_match(n: &Nfa, hay: &[u8]) -> bool {
+        n.is_match(hay, &mut Budget::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn it_matches_like_the_nfa() {
+        let n = nfa("\\d+ (apple|orange)s?");
+        let mut cache = Cache::new(&n);
+
+        for hay in ["3 apples", "12 orange", "apple 3", "1 pear", "a 1 orange"] {
+            assert_eq!(
+                search(&n, &mut cache, hay.as_bytes(), None),
+                Some(nfa_match(&n, hay.as_bytes()))
+            );
+        }
+    }
+
+    #[test]
+    fn it_reuses_states_across_searches() {
+        let n = nfa("ab");
+        let mut cache = Cache::new(&n);
+
+        assert_eq!(search(&n, &mut cache, b"xxab", None), Some(true));
+        let built = cache.states.len();
+
+        assert_eq!(search(&n, &mut cache, b"xxab", None), Some(true));
+        assert_eq!(cache.states.len(), built);
+    }
+
+    #[test]
+    fn it_gives_up_when_the_cache_thrashes() {
+        // The DFA for this pattern has an exponential number of states.
+        let n = nfa("a(a|b)(a|b)(a|b)(a|b)(a|b)(a|b)c");
+        let mut cache = Cache::with_capacity(&n, 0);
+        let hay = "abbabaabbbaababbbaaabaabbabbbab";
+
+        assert_eq!(search(&n, &mut cache, hay.as_bytes(), None), None);
+        assert!(!nfa_match(&n, hay.as_bytes()));
+    }
+
+    #[test]
+    fn it_finds_where_the_first_match_ends() {
+        let n = nfa("(\\d+|ab)");
+        let mut cache = Cache::new(&n);
+        let mut find = |hay: &[u8], at| {
+            find_end(&n, &mut cache, hay, at, None, &mut Budget::unlimited()).unwrap()
+        };
+
+        assert_eq!(find(b"xab 12", 0), Some(Some(3)));
+        assert_eq!(find(b"xab 12", 2), Some(Some(5)));
+        assert_eq!(find(b"xab 12", 6), Some(None));
+    }
+
+    #[test]
+    fn it_skips_ahead_to_the_prefix() {
+        let patterns = translate(&parse_pattern("ab\\d").patterns());
+        let n = Nfa::new(&patterns, false, false);
+        let pre = Prefilter::new(&patterns, false, false);
+        let mut cache = Cache::new(&n);
+
+        for hay in ["xxab1", "abab", "aab2", "ab", "a", ""] {
+            assert_eq!(
+                search(&n, &mut cache, hay.as_bytes(), pre.as_ref()),
+                Some(nfa_match(&n, hay.as_bytes())),
+            );
+        }
+    }
+}
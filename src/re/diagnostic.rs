@@ -0,0 +1,77 @@
+use super::ParseError;
+use std::fmt;
+
+/// Renders a [`ParseError`] like a compiler diagnostic: the message, then the
+/// expression with the offending part underlined by carets.
+///
+/// ```text
+/// error: unclosed group
+///  | x(?:a|b
+///  |  ^^^ this group is never closed
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostic<'e> {
+    error: &'e ParseError,
+    expr: &'e str,
+}
+
+impl ParseError {
+    /// Returns the diagnostic for this error in `expr`, the expression it was
+    /// returned for.
+    pub fn diagnostic<'e>(&'e self, expr: &'e str) -> Diagnostic<'e> {
+        Diagnostic { error: self, expr }
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = self.error.kind;
+        let span = self.error.span;
+        // Spans are byte ranges, but carets line up with chars.
+        let column = |at: usize| self.expr.get(..at).map_or(at, |s| s.chars().count());
+        let start = column(span.start);
+        let width = column(span.end).saturating_sub(start).max(1);
+
+        writeln!(f, "error: {}", kind.message())?;
+        writeln!(f, " | {}", self.expr)?;
+        writeln!(f, " | {:start$}{} {}", "", "^".repeat(width), kind.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Regex;
+
+    fn render(expr: &str) -> String {
+        let err = Regex::new(expr).unwrap_err();
+        err.diagnostic(expr).to_string()
+    }
+
+    #[test]
+    fn it_underlines_the_offending_part() {
+        assert_eq!(
+            render("x(?:a|b"),
+            "error: unclosed group\n | x(?:a|b\n |  ^^^ this group is never closed\n"
+        );
+        assert_eq!(
+            render("ab\\"),
+            "error: invalid escape\n | ab\\\n |   ^ not a supported escape\n"
+        );
+        assert_eq!(
+            render("a\\qb"),
+            "error: invalid escape\n | a\\qb\n |  ^^ not a supported escape\n"
+        );
+        assert_eq!(
+            render("[z-a]"),
+            "error: invalid range\n | [z-a]\n |  ^^^ the range must go from a char to a later one\n"
+        );
+    }
+
+    #[test]
+    fn it_counts_columns_in_chars() {
+        assert_eq!(
+            render("été)"),
+            "error: unmatched closing parenthesis\n | été)\n |    ^ no group to close here\n"
+        );
+    }
+}
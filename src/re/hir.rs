@@ -0,0 +1,331 @@
+use super::charset::{CharSet, MAX_CHAR};
+use super::pattern::{Pattern, PatternKind};
+
+/// An owned, simplified form of the parsed patterns that every engine is
+/// compiled from.
+///
+/// Unlike [`Pattern`], a node does not borrow the expression, literals are
+/// whole strings and every class is a [`CharSet`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hir {
+    /// One or more chars matched one after the other.
+    Literal(String),
+    /// Any single unit of the set.
+    Class(CharSet),
+    Star(Box<Hir>),
+    Plus(Box<Hir>),
+    Optional(Box<Hir>),
+    /// A capturing group, with the name it was given if any.
+    Capture {
+        name: Option<String>,
+        branches: Vec<Vec<Hir>>,
+    },
+    /// A group that only delimits its branches.
+    Group(Vec<Vec<Hir>>),
+}
+
+impl Hir {
+    /// Returns the branches of a group, whether it captures or not.
+    pub fn branches(&self) -> Option<&[Vec<Hir>]> {
+        match self {
+            Self::Capture { branches, .. } | Self::Group(branches) => Some(branches),
+            _ => None,
+        }
+    }
+
+    fn has_captures(&self) -> bool {
+        group_names(std::slice::from_ref(self)).len() > 1
+    }
+}
+
+/// Lowers parsed patterns into the HIR and optimizes it.
+pub fn translate(patterns: &[Pattern]) -> Vec<Hir> {
+    concat(patterns.iter().map(lower).collect())
+}
+
+fn lower(pat: &Pattern) -> Hir {
+    let branches = |branches: &[Vec<Pattern>]| -> Vec<Vec<Hir>> {
+        branches
+            .iter()
+            .map(|branch| branch.iter().map(lower).collect())
+            .collect()
+    };
+
+    match &pat.kind {
+        PatternKind::Lit(lit) => Hir::Literal(lit.to_string()),
+        PatternKind::Byte(b) if b.is_ascii() => Hir::Literal((*b as char).to_string()),
+        PatternKind::MoreThanZero(p) => Hir::Star(Box::new(lower(p))),
+        PatternKind::MoreThanOne(p) => Hir::Plus(Box::new(lower(p))),
+        PatternKind::ZeroOrOne(p) => Hir::Optional(Box::new(lower(p))),
+        PatternKind::Alternation(b) => Hir::Capture {
+            name: None,
+            branches: branches(b),
+        },
+        PatternKind::Named(name, b) => Hir::Capture {
+            name: Some(name.to_string()),
+            branches: branches(b),
+        },
+        PatternKind::NonCapturing(b) => Hir::Group(branches(b)),
+        _ => Hir::Class(pat.char_set().expect("single-char pattern")),
+    }
+}
+
+// Optimizes each node of a concatenation, then splices in groups with a
+// single branch, merges adjacent literals and collapses `x*x*` into `x*`.
+fn concat(hirs: Vec<Hir>) -> Vec<Hir> {
+    let mut out = vec![];
+
+    for hir in hirs {
+        match optimize(hir) {
+            Hir::Group(mut branches) if branches.len() == 1 => {
+                for hir in branches.pop().unwrap_or_default() {
+                    push(&mut out, hir);
+                }
+            }
+            hir => push(&mut out, hir),
+        }
+    }
+
+    out
+}
+
+fn push(out: &mut Vec<Hir>, hir: Hir) {
+    match (out.last_mut(), &hir) {
+        (Some(Hir::Literal(prev)), Hir::Literal(lit)) => prev.push_str(lit),
+        // Dropping a repetition would also drop its groups.
+        (Some(Hir::Star(prev)), Hir::Star(next)) if **prev == **next && !next.has_captures() => {}
+        _ => out.push(hir),
+    }
+}
+
+fn optimize(hir: Hir) -> Hir {
+    match hir {
+        Hir::Class(set) => match single_char(&set) {
+            Some(c) => Hir::Literal(c.to_string()),
+            None => Hir::Class(set),
+        },
+        Hir::Star(h) => Hir::Star(Box::new(unwrap(optimize(*h)))),
+        Hir::Plus(h) => Hir::Plus(Box::new(unwrap(optimize(*h)))),
+        Hir::Optional(h) => Hir::Optional(Box::new(unwrap(optimize(*h)))),
+        Hir::Capture { name, branches } => Hir::Capture {
+            name,
+            branches: alternate(branches),
+        },
+        Hir::Group(branches) => Hir::Group(alternate(branches)),
+        hir => hir,
+    }
+}
+
+// A group holding a single node is that node.
+fn unwrap(hir: Hir) -> Hir {
+    match hir {
+        Hir::Group(mut branches) if branches.len() == 1 && branches[0].len() == 1 => {
+            branches[0].pop().unwrap_or(Hir::Group(vec![]))
+        }
+        hir => hir,
+    }
+}
+
+fn single_char(set: &CharSet) -> Option<char> {
+    match set.ranges() {
+        [(lo, hi)] if lo == hi && *lo <= MAX_CHAR => char::from_u32(*lo),
+        _ => None,
+    }
+}
+
+// Optimizes every branch, then moves the literal prefix common to all of them
+// in front of a group of what is left, keeping the branches in order.
+fn alternate(branches: Vec<Vec<Hir>>) -> Vec<Vec<Hir>> {
+    let branches: Vec<Vec<Hir>> = branches.into_iter().map(concat).collect();
+
+    if branches.len() < 2 {
+        return branches;
+    }
+
+    let prefix = branches
+        .iter()
+        .map(|branch| match branch.first() {
+            Some(Hir::Literal(lit)) => lit.as_str(),
+            _ => "",
+        })
+        .reduce(common_prefix)
+        .unwrap_or_default()
+        .to_string();
+
+    if prefix.is_empty() {
+        return branches;
+    }
+
+    let rest = branches
+        .into_iter()
+        .map(|mut branch| {
+            if let Some(Hir::Literal(lit)) = branch.first_mut() {
+                lit.drain(..prefix.len());
+
+                if lit.is_empty() {
+                    branch.remove(0);
+                }
+            }
+
+            branch
+        })
+        .collect();
+
+    vec![vec![Hir::Literal(prefix), Hir::Group(alternate(rest))]]
+}
+
+fn common_prefix<'s>(a: &'s str, b: &str) -> &'s str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i);
+
+    &a[..len]
+}
+
+/// Returns the name of every capturing group, numbered from 1 in the order of
+/// their opening parentheses. Group 0 is the whole match and has no name.
+pub fn group_names(hirs: &[Hir]) -> Vec<Option<&str>> {
+    fn visit<'h>(hirs: &'h [Hir], names: &mut Vec<Option<&'h str>>) {
+        for hir in hirs {
+            match hir {
+                Hir::Star(h) | Hir::Plus(h) | Hir::Optional(h) => {
+                    visit(std::slice::from_ref(h.as_ref()), names)
+                }
+                Hir::Capture { name, branches } => {
+                    names.push(name.as_deref());
+
+                    for branch in branches {
+                        visit(branch, names);
+                    }
+                }
+                Hir::Group(branches) => {
+                    for branch in branches {
+                        visit(branch, names);
+                    }
+                }
+                Hir::Literal(_) | Hir::Class(_) => {}
+            }
+        }
+    }
+
+    let mut names = vec![None];
+    visit(hirs, &mut names);
+    names
+}
+
+/// Returns the string matched by `hirs` if they are all literals.
+pub fn literal_str(hirs: &[Hir]) -> Option<String> {
+    hirs.iter()
+        .map(|hir| match hir {
+            Hir::Literal(lit) => Some(lit.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the string matched by each branch, in order, if every branch is
+/// a literal. Branches that had their common prefix factored out are expanded
+/// back into whole literals.
+pub fn branch_literals(branches: &[Vec<Hir>]) -> Option<Vec<String>> {
+    let mut literals = vec![];
+
+    for branch in branches {
+        match branch.as_slice() {
+            [Hir::Literal(prefix), Hir::Group(rest)] => {
+                for lit in branch_literals(rest)? {
+                    literals.push(format!("{prefix}{lit}"));
+                }
+            }
+            branch => literals.push(literal_str(branch)?),
+        }
+    }
+
+    Some(literals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    fn hir(expr: &str) -> Vec<Hir> {
+        translate(&parse_pattern(expr).patterns())
+    }
+
+    fn lit(s: &str) -> Hir {
+        Hir::Literal(s.to_string())
+    }
+
+    #[test]
+    fn it_merges_literals_and_single_char_classes() {
+        assert_eq!(hir("ab[c]\\x64e"), [lit("abcde")]);
+        assert_eq!(
+            hir("a\\db"),
+            [lit("a"), Hir::Class(CharSet::digit()), lit("b")]
+        );
+        assert_eq!(hir("\\xFF"), [Hir::Class(CharSet::byte(0xFF))]);
+    }
+
+    #[test]
+    fn it_factors_common_prefixes() {
+        assert_eq!(
+            hir("x(?:abc|abd|ab)"),
+            [
+                lit("xab"),
+                Hir::Group(vec![vec![lit("c")], vec![lit("d")], vec![]]),
+            ]
+        );
+
+        let Hir::Capture { branches, .. } = &hir("(GET|GEM\\d)")[0] else {
+            panic!("expected a capture");
+        };
+        assert_eq!(
+            branches,
+            &[vec![
+                lit("GE"),
+                Hir::Group(vec![
+                    vec![lit("T")],
+                    vec![lit("M"), Hir::Class(CharSet::digit())]
+                ]),
+            ]]
+        );
+
+        assert_eq!(hir("(?:ab|cd)").len(), 1);
+    }
+
+    #[test]
+    fn it_expands_factored_branches_into_literals() {
+        let Hir::Capture { branches, .. } = &hir("(PUT|POST|PATCH|P)")[0] else {
+            panic!("expected a capture");
+        };
+        assert_eq!(
+            branch_literals(branches).unwrap(),
+            ["PUT", "POST", "PATCH", "P"]
+        );
+        assert_eq!(branch_literals(&[hir("a\\d")]), None);
+    }
+
+    #[test]
+    fn it_collapses_repeated_stars() {
+        let star = Hir::Star(Box::new(Hir::Class(CharSet::digit())));
+        assert_eq!(hir("\\d*\\d*a"), [star, lit("a")]);
+        assert_eq!(hir("(a)*(a)*").len(), 2);
+    }
+
+    #[test]
+    fn it_removes_redundant_groups() {
+        assert_eq!(hir("(?:ab)c"), [lit("abc")]);
+        assert_eq!(hir("(?:a)+"), [Hir::Plus(Box::new(lit("a")))]);
+        assert_eq!(hir("(?:ab)?").len(), 1);
+    }
+
+    #[test]
+    fn it_numbers_groups_in_order() {
+        assert_eq!(
+            group_names(&hir("(a(?P<b>b)(?:c(d))+)?(?<e>e)")),
+            [None, None, Some("b"), None, Some("e")]
+        );
+    }
+}
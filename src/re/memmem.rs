@@ -0,0 +1,151 @@
+use super::simd::ByteClass;
+
+/// Substring search that scans for the rarest byte of the needle many bytes at
+/// a time, and checks the needle around every occurrence of it.
+///
+/// When that byte turns out to be common in the haystack, the search switches
+/// to the Boyer-Moore-Horspool algorithm, where the byte under the last
+/// position of the needle decides how far the needle can be shifted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finder {
+    needle: Vec<u8>,
+    skip: Vec<usize>,
+    // The offset of the rarest byte in the needle, and that byte.
+    rare: Option<(usize, ByteClass)>,
+}
+
+impl Finder {
+    pub fn new(needle: &[u8]) -> Self {
+        let mut skip = vec![needle.len().max(1); 256];
+
+        if let Some((_, init)) = needle.split_last() {
+            for (i, &b) in init.iter().enumerate() {
+                skip[b as usize] = init.len() - i;
+            }
+        }
+
+        let rare = (0..needle.len())
+            .min_by_key(|&i| frequency(needle[i]))
+            .map(|i| (i, ByteClass::byte(needle[i])));
+
+        Self {
+            needle: needle.to_vec(),
+            skip,
+            rare,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    pub fn find(&self, hay: &[u8]) -> Option<usize> {
+        self.find_at(hay, 0)
+    }
+
+    /// Finds the first occurrence of the needle at or after `from`.
+    pub fn find_at(&self, hay: &[u8], from: usize) -> Option<usize> {
+        let Some((offset, rare)) = &self.rare else {
+            return (from <= hay.len()).then_some(from);
+        };
+
+        let n = self.needle.len();
+        let mut pos = from;
+        let mut misses = 0;
+
+        while pos + n <= hay.len() {
+            let start = rare.find(hay, pos + offset)? - offset;
+
+            if start + n > hay.len() {
+                return None;
+            }
+
+            if hay[start..start + n] == self.needle {
+                return Some(start);
+            }
+
+            pos = start + 1;
+            misses += 1;
+
+            // Give up on the scan once candidates keep coming every few bytes.
+            if misses > 16 && misses * 8 > pos - from {
+                return self.horspool(hay, pos);
+            }
+        }
+
+        None
+    }
+
+    fn horspool(&self, hay: &[u8], from: usize) -> Option<usize> {
+        let (&last, init) = self.needle.split_last()?;
+        let mut pos = from;
+
+        while pos + self.needle.len() <= hay.len() {
+            let b = hay[pos + init.len()];
+
+            if b == last && &hay[pos..pos + init.len()] == init {
+                return Some(pos);
+            }
+
+            pos += self.skip[b as usize];
+        }
+
+        None
+    }
+}
+
+// A rough rank of how often a byte shows up in text, lowest for the rarest.
+fn frequency(b: u8) -> u8 {
+    match b {
+        b' ' | b'e' | b't' | b'a' | b'o' | b'i' | b'n' | b's' | b'r' | b'h' => 5,
+        b'a'..=b'z' => 4,
+        b'0'..=b'9' | b'A'..=b'Z' | b'\t' | b'\n' | b'\r' => 3,
+        b'!'..=b'~' => 2,
+        0x80..=0xFF => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_needles() {
+        let f = Finder::new(b"needle");
+        assert_eq!(f.find(b"haystack with a needle in it"), Some(16));
+        assert_eq!(f.find(b"needle"), Some(0));
+        assert_eq!(f.find(b"needl"), None);
+        assert_eq!(f.find(b"a needle and another needle"), Some(2));
+        assert_eq!(f.find_at(b"a needle and another needle", 3), Some(21));
+    }
+
+    #[test]
+    fn it_finds_repetitive_needles() {
+        let f = Finder::new(b"aab");
+        assert_eq!(f.find(b"aaaaaab"), Some(4));
+        assert_eq!(f.find(b"abaabaa"), Some(2));
+        assert_eq!(f.find(b"aaaaaaa"), None);
+    }
+
+    #[test]
+    fn it_finds_short_needles() {
+        assert_eq!(Finder::new(b"x").find(b"abcxd"), Some(3));
+        assert_eq!(Finder::new(b"x").find_at(b"abcxd", 4), None);
+        assert_eq!(Finder::new(b"").find_at(b"abc", 3), Some(3));
+        assert_eq!(Finder::new(b"").find_at(b"abc", 4), None);
+    }
+
+    #[test]
+    fn it_finds_needles_whose_rarest_byte_is_common() {
+        let f = Finder::new(b"x-y");
+        let mut hay = b"x-".repeat(100);
+        hay.extend_from_slice(b"x-y");
+        assert_eq!(f.find(&hay), Some(200));
+
+        let f = Finder::new(b"Q");
+        let hay = [b"a".repeat(70), b"Q".to_vec()].concat();
+        assert_eq!(f.find(&hay), Some(70));
+        assert_eq!(f.find_at(&hay, 71), None);
+    }
+}
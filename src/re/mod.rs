@@ -1,78 +1,645 @@
+mod aho;
+mod budget;
+mod builder;
+pub mod bytes;
+mod captures;
+mod charset;
+mod dense;
+mod dfa;
+mod diagnostic;
+mod hir;
 mod letter;
+mod memmem;
+mod nfa;
 mod pattern;
+mod pikevm;
+mod pool;
+mod prefilter;
+mod replace;
+mod reverse;
+mod set;
+mod shift_and;
+mod simd;
+mod split;
+mod stream;
+mod utf8;
 
+pub use builder::{RegexBuilder, RegexSetBuilder};
+pub use captures::{CaptureMatches, Captures};
+pub use dense::DenseDfa;
+pub use diagnostic::Diagnostic;
+pub use pattern::{ParseError, ParseErrorKind, Span};
+pub use pikevm::MatchKind;
+pub use replace::Replacer;
+pub use set::RegexSet;
+pub use split::{Split, SplitN};
+pub use stream::{Stream, StreamMatch};
+
+use crate::Result;
+use aho::AhoCorasick;
+use budget::{Budget, Limits};
+use hir::{branch_literals, group_names, literal_str, translate, Hir};
 use letter::Letters;
-use pattern::{parse_pattern, search_match_size, Pattern};
+use memmem::Finder;
+use nfa::Nfa;
+use pattern::{parse_pattern_at, Concat};
+use pool::Pool;
+use prefilter::{LiteralKind, Prefilter};
+use reverse::{Reverse, ReverseInner, ReverseSuffix};
+use shift_and::ShiftAnd;
+use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
-pub struct Regex<'a> {
-    start_anchor: bool,
-    end_anchor: bool,
-    patterns: Vec<Pattern<'a>>,
+#[derive(Debug, Clone)]
+enum Strategy {
+    /// Runs the lazy DFA, falling back to the NFA.
+    Automaton,
+    /// The whole pattern is a single literal.
+    Literal(Box<Finder>),
+    /// Every match is one of a few literals.
+    Literals(Box<AhoCorasick>),
+    /// The pattern ends in `$` but does not start with `^`, so it is matched
+    /// backwards from the end of the haystack.
+    ReverseAnchored(Box<Reverse>),
+    /// Every match ends with a literal, so the pattern is matched backwards
+    /// from each occurrence of it.
+    ReverseSuffix(Box<ReverseSuffix>),
+    /// Every match contains a literal, so the pattern is matched backwards and
+    /// forwards from each occurrence of it.
+    ReverseInner(Box<ReverseInner>),
+    /// The pattern is short enough for the bit-parallel matcher.
+    ShiftAnd(Box<ShiftAnd>),
 }
 
-impl<'a> Regex<'a> {
-    pub fn new(expr: &'a str) -> Self {
-        let start_anchor = expr.starts_with('^');
-        let expr = if start_anchor { &expr[1..] } else { expr };
+impl Strategy {
+    fn new(
+        patterns: &[Hir],
+        start_anchor: bool,
+        end_anchor: bool,
+        prefilter: Option<&Prefilter>,
+    ) -> Self {
+        if let Some(literal) = literal_str(patterns) {
+            return Self::Literal(Box::new(Finder::new(literal.as_bytes())));
+        }
 
-        let end_anchor = expr.ends_with('$');
-        let expr = if end_anchor {
-            &expr[..expr.len() - 1]
-        } else {
-            expr
+        let literals = match patterns {
+            [hir] => hir.branches().and_then(branch_literals),
+            // A factored group without captures is spliced into the patterns.
+            [Hir::Literal(_), Hir::Group(_)] => branch_literals(&[patterns.to_vec()]),
+            _ => None,
         };
 
-        let parsed = parse_pattern(expr);
+        if let Some(literals) = literals
+            .filter(|literals| literals.iter().all(|lit| !lit.is_empty()))
+            .filter(|_| !start_anchor && !end_anchor)
+        {
+            return Self::Literals(Box::new(AhoCorasick::new(literals)));
+        }
 
-        // TODO:
-        // Error handling when rest is not empty
-        if !parsed.completed() {
-            panic!("Cannot parse regexp completely!");
+        // With a start anchor, matching forwards only tries a single start
+        // position already.
+        if !start_anchor && end_anchor {
+            return Self::ReverseAnchored(Box::new(Reverse::new(patterns, false)));
         }
 
-        Self {
+        if let Some(suffix) = prefilter
+            .and_then(Prefilter::suffix)
+            .filter(|_| !start_anchor && !end_anchor)
+        {
+            return Self::ReverseSuffix(Box::new(ReverseSuffix::new(patterns, suffix)));
+        }
+
+        if let Some(ri) = prefilter
+            .and_then(Prefilter::inner)
+            .filter(|_| !start_anchor && !end_anchor)
+            .and_then(|inner| ReverseInner::new(patterns, inner))
+        {
+            return Self::ReverseInner(Box::new(ri));
+        }
+
+        match ShiftAnd::new(patterns, start_anchor, end_anchor) {
+            Some(sa) => Self::ShiftAnd(Box::new(sa)),
+            None => Self::Automaton,
+        }
+    }
+}
+
+// A whole expression, parsed.
+struct Expr {
+    hir: Vec<Hir>,
+    start_anchor: bool,
+    end_anchor: bool,
+    // The canonical text of the expression, see `Regex`'s `Display`.
+    text: String,
+}
+
+// Fails with every error found in `expr`, see `Regex::diagnose`.
+fn parse_expr(expr: &str) -> std::result::Result<Expr, Vec<ParseError>> {
+    let start_anchor = expr.starts_with('^');
+    let offset = start_anchor as usize;
+    let expr = &expr[offset..];
+
+    // A `$` after an odd number of backslashes is escaped.
+    let end_anchor = expr.strip_suffix('$').is_some_and(|rest| {
+        let escapes = rest.len() - rest.trim_end_matches('\\').len();
+        escapes % 2 == 0
+    });
+    let expr = if end_anchor {
+        &expr[..expr.len() - 1]
+    } else {
+        expr
+    };
+
+    let patterns = parse_pattern_at(expr, offset)?;
+    let text = format!(
+        "{}{}{}",
+        if start_anchor { "^" } else { "" },
+        Concat(&patterns),
+        if end_anchor { "$" } else { "" },
+    );
+
+    Ok(Expr {
+        hir: translate(&patterns),
+        start_anchor,
+        end_anchor,
+        text,
+    })
+}
+
+/// A match of a regex in a haystack, with the byte offsets of its span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    pub start: usize,
+    pub end: usize,
+    pub text: &'h str,
+}
+
+/// A compiled regex. It owns everything it needs, so it can be cloned, cached
+/// and shared between threads independently of the expression string.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    strategy: Strategy,
+    nfa: Nfa,
+    prefilter: Option<Prefilter>,
+    cache: Pool<dfa::Cache>,
+    limits: Limits,
+    kind: MatchKind,
+    // The name of every capturing group, the whole match being group 0.
+    names: Arc<[Option<String>]>,
+    text: Arc<str>,
+}
+
+impl Regex {
+    /// Fails with the first problem found in `expr` if it is not a valid
+    /// pattern.
+    pub fn new(expr: &str) -> std::result::Result<Self, ParseError> {
+        Self::with_options(expr, Limits::default(), MatchKind::default())
+    }
+
+    /// Returns every problem found in `expr`, in the order they appear in it,
+    /// or nothing if it is a valid pattern. Unlike [`Regex::new`], this does
+    /// not stop at the first one.
+    pub fn diagnose(expr: &str) -> Vec<ParseError> {
+        parse_expr(expr).err().unwrap_or_default()
+    }
+
+    fn with_options(
+        expr: &str,
+        limits: Limits,
+        kind: MatchKind,
+    ) -> std::result::Result<Self, ParseError> {
+        let Expr {
+            hir,
             start_anchor,
             end_anchor,
-            patterns: parsed.patterns(),
-        }
+            text,
+        } = parse_expr(expr).map_err(|errors| errors[0])?;
+        let nfa = Nfa::new(&hir, start_anchor, end_anchor);
+        let prefilter = Prefilter::new(&hir, start_anchor, end_anchor);
+        let names = group_names(&hir)
+            .into_iter()
+            .map(|name| name.map(str::to_string))
+            .collect();
+
+        Ok(Self {
+            strategy: Strategy::new(&hir, start_anchor, end_anchor, prefilter.as_ref()),
+            nfa,
+            prefilter,
+            cache: Pool::new(),
+            limits,
+            kind,
+            names,
+            text: text.into(),
+        })
     }
 
+    /// A search that runs out of the limits set with [`RegexBuilder`] counts
+    /// as no match. Use [`Regex::try_is_match`] to tell the two apart.
     pub fn is_match(&self, s: &str) -> bool {
-        let mut cur_pos: usize = 0;
-
-        if !self.start_anchor {
-            // Search the first position
-            cur_pos = match self.patterns.first().and_then(|p| p.search_match_pos(s)) {
-                Some(pos) => pos,
-                None => {
-                    return false;
+        self.try_is_match(s).unwrap_or(false)
+    }
+
+    /// Like [`Regex::is_match`], but fails when the search exceeds the step
+    /// limit or the timeout set with [`RegexBuilder`].
+    pub fn try_is_match(&self, s: &str) -> Result<bool> {
+        self.try_is_match_bytes(s.as_bytes())
+    }
+
+    /// Returns the leftmost match in `s`. A search that runs out of its
+    /// limits counts as no match, like in [`Regex::is_match`].
+    pub fn find<'h>(&self, s: &'h str) -> Option<Match<'h>> {
+        self.try_find(s).unwrap_or(None)
+    }
+
+    pub fn try_find<'h>(&self, s: &'h str) -> Result<Option<Match<'h>>> {
+        let span = self.try_find_at_bytes(s.as_bytes(), 0, &mut [])?;
+
+        Ok(span.map(|(start, end)| Match {
+            start,
+            end,
+            text: &s[start..end],
+        }))
+    }
+
+    /// Returns every non-overlapping match in `s` from left to right. After an
+    /// empty match, the search resumes one char further. The iteration stops
+    /// early if a search runs out of its limits, see [`Regex::try_find_iter`].
+    pub fn find_iter<'r, 'h>(&'r self, s: &'h str) -> FindMatches<'r, 'h> {
+        FindMatches {
+            spans: Spans::new(self, s.as_bytes()),
+            hay: s,
+        }
+    }
+
+    /// Like [`Regex::find_iter`], but yields an error and stops when a search
+    /// exceeds the step limit or the timeout set with [`RegexBuilder`].
+    pub fn try_find_iter<'r, 'h>(&'r self, s: &'h str) -> TryFindMatches<'r, 'h> {
+        TryFindMatches {
+            spans: Spans::new(self, s.as_bytes()),
+            hay: s,
+        }
+    }
+
+    // Finds the leftmost match at or after `at`, and fills `slots` with the
+    // spans of as many capture groups as it has room for, starting at group 1.
+    fn try_find_at_bytes(
+        &self,
+        hay: &[u8],
+        at: usize,
+        slots: &mut [Option<usize>],
+    ) -> Result<Option<(usize, usize)>> {
+        if self
+            .prefilter
+            .as_ref()
+            .is_some_and(|pre| !pre.may_match(hay))
+        {
+            return Ok(None);
+        }
+
+        self.find_from(hay, at, slots)
+    }
+
+    // Like `try_find_at_bytes`, but for a haystack the prefilter let through
+    // already. The PikeVM only runs once the DFA has found that a match ends
+    // somewhere, from the first place one may start.
+    fn find_from(
+        &self,
+        hay: &[u8],
+        mut at: usize,
+        slots: &mut [Option<usize>],
+    ) -> Result<Option<(usize, usize)>> {
+        if let Some(pre) = self
+            .prefilter
+            .as_ref()
+            .filter(|pre| pre.kind() == LiteralKind::Prefix && !self.nfa.start_anchor())
+        {
+            match pre.find(hay, at) {
+                Some(found) => at = found,
+                None => return Ok(None),
+            }
+        }
+
+        let mut budget = Budget::new(self.limits);
+        let end = dfa::find_end(
+            &self.nfa,
+            &mut self.cache.get(|| dfa::Cache::new(&self.nfa)),
+            hay,
+            at,
+            self.prefilter.as_ref(),
+            &mut budget,
+        )?;
+
+        if end == Some(None) {
+            return Ok(None);
+        }
+
+        pikevm::find_at(&self.nfa, hay, at, self.kind, slots, &mut budget)
+    }
+
+    fn try_is_match_bytes(&self, hay: &[u8]) -> Result<bool> {
+        // Literal searches take linear time at worst, so only the automata
+        // are held to the limits.
+        match &self.strategy {
+            Strategy::Literal(finder) => {
+                let needle = finder.needle();
+
+                return Ok(match (self.nfa.start_anchor(), self.nfa.end_anchor()) {
+                    (true, true) => hay == needle,
+                    (true, false) => hay.starts_with(needle),
+                    (false, true) => hay.ends_with(needle),
+                    (false, false) => finder.find(hay).is_some(),
+                });
+            }
+            Strategy::Literals(ac) => return Ok(ac.is_match(hay)),
+            Strategy::ReverseAnchored(_)
+            | Strategy::ReverseSuffix(_)
+            | Strategy::ReverseInner(_)
+            | Strategy::ShiftAnd(_)
+            | Strategy::Automaton => {}
+        }
+
+        if self
+            .prefilter
+            .as_ref()
+            .is_some_and(|pre| !pre.may_match(hay))
+        {
+            return Ok(false);
+        }
+
+        let mut budget = Budget::new(self.limits);
+
+        match &self.strategy {
+            Strategy::ReverseAnchored(rev) => return rev.is_match_at(hay, hay.len(), &mut budget),
+            Strategy::ReverseSuffix(rs) => {
+                if let Some(matched) = rs.is_match(hay, &mut budget)? {
+                    return Ok(matched);
                 }
-            };
+            }
+            Strategy::ReverseInner(ri) => {
+                if let Some(matched) = ri.is_match(hay, &mut budget)? {
+                    return Ok(matched);
+                }
+            }
+            Strategy::ShiftAnd(sa) => {
+                // No match starts before the first occurrence of the prefix.
+                let from = match &self.prefilter {
+                    Some(pre) if pre.kind() == LiteralKind::Prefix && !self.nfa.start_anchor() => {
+                        pre.find(hay, 0).unwrap_or(0)
+                    }
+                    _ => 0,
+                };
+
+                return sa.is_match(&hay[from..], &mut budget);
+            }
+            _ => {}
+        }
+
+        let mut cache = self.cache.get(|| dfa::Cache::new(&self.nfa));
+
+        match dfa::is_match(
+            &self.nfa,
+            &mut cache,
+            hay,
+            self.prefilter.as_ref(),
+            &mut budget,
+        )? {
+            Some(matched) => Ok(matched),
+            None => self.nfa.is_match(hay, &mut budget),
+        }
+    }
+
+    /// Compiles the regex ahead of time into a minimized DFA whose transition
+    /// table takes at most `size_limit` bytes.
+    pub fn to_dense_dfa(&self, size_limit: usize) -> Result<DenseDfa> {
+        DenseDfa::new(&self.nfa, size_limit)
+    }
+}
+
+/// Writes the expression as it was understood, in canonical form: every
+/// metacharacter that stands for itself is escaped and every byte escape is
+/// upper case. The text parses back to an equal regex.
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// The spans of the successive non-overlapping matches in a haystack. An empty
+/// match right where the previous match ends is skipped.
+#[derive(Debug)]
+struct Spans<'r, 'h> {
+    regex: &'r Regex,
+    hay: &'h [u8],
+    // Where the next search starts, or `None` once the haystack is exhausted.
+    pos: Option<usize>,
+    last_end: Option<usize>,
+    // The capture groups of the last match, starting at group 1.
+    slots: Vec<Option<usize>>,
+}
+
+impl<'r, 'h> Spans<'r, 'h> {
+    fn new(regex: &'r Regex, hay: &'h [u8]) -> Self {
+        // The prefilter looks at the whole haystack, so it only runs once.
+        let may_match = regex
+            .prefilter
+            .as_ref()
+            .map_or(true, |pre| pre.may_match(hay));
+
+        Self {
+            regex,
+            hay,
+            pos: may_match.then_some(0),
+            last_end: None,
+            slots: vec![],
+        }
+    }
+
+    fn with_captures(regex: &'r Regex, hay: &'h [u8]) -> Self {
+        Self {
+            slots: vec![None; 2 * (regex.names.len() - 1)],
+            ..Self::new(regex, hay)
         }
-        let s = &s[cur_pos..];
-        let matched_pos = match search_match_size(&self.patterns, s) {
-            Some(size) => size,
-            None => {
-                return false;
+    }
+}
+
+impl Iterator for Spans<'_, '_> {
+    type Item = Result<(usize, usize)>;
+
+    // A search that runs out of its limits is yielded as an error and ends
+    // the iteration.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let pos = self.pos.take()?;
+            let (start, end) = match self.regex.find_from(self.hay, pos, &mut self.slots) {
+                Ok(span) => span?,
+                Err(err) => return Some(Err(err)),
+            };
+
+            self.pos = if start == end {
+                utf8::decode(&self.hay[end..]).map(|(_, len)| end + len)
+            } else {
+                Some(end)
+            };
+
+            if start == end && self.last_end == Some(end) {
+                continue;
             }
-        };
 
-        if self.end_anchor {
-            return matched_pos == s.len();
+            self.last_end = Some(end);
+            return Some(Ok((start, end)));
         }
+    }
+}
 
-        true
+/// An iterator over the matches of a [`Regex`], see [`Regex::find_iter`].
+#[derive(Debug)]
+pub struct FindMatches<'r, 'h> {
+    spans: Spans<'r, 'h>,
+    hay: &'h str,
+}
+
+impl<'h> Iterator for FindMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.spans.next()?.ok()?;
+
+        Some(Match {
+            start,
+            end,
+            text: &self.hay[start..end],
+        })
+    }
+}
+
+/// An iterator over the matches of a [`Regex`] that reports searches which
+/// run out of their limits, see [`Regex::try_find_iter`].
+#[derive(Debug)]
+pub struct TryFindMatches<'r, 'h> {
+    spans: Spans<'r, 'h>,
+    hay: &'h str,
+}
+
+impl<'h> Iterator for TryFindMatches<'_, 'h> {
+    type Item = Result<Match<'h>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let span = self.spans.next()?;
+
+        Some(span.map(|(start, end)| Match {
+            start,
+            end,
+            text: &self.hay[start..end],
+        }))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn it_rejects_invalid_patterns() {
+        use ParseErrorKind::*;
+
+        let error = |expr| Regex::new(expr).map(|_| ()).map_err(|e| (e.kind, e.span));
+        let span = |start, end| Span { start, end };
+
+        assert_eq!(error("a|b"), Err((BareAlternation, span(1, 2))));
+        assert_eq!(error("^ab\\"), Err((BadEscape, span(3, 4))));
+        assert_eq!(error("x(?:a|b"), Err((UnclosedGroup, span(1, 4))));
+        assert_eq!(error("(a))"), Err((UnopenedGroup, span(3, 4))));
+        assert_eq!(error("(a]b)"), Err((UnopenedBracket, span(2, 3))));
+        assert_eq!(error("a[^bc"), Err((UnclosedBracket, span(1, 3))));
+        assert_eq!(error("^*a"), Err((DanglingQuantifier, span(1, 2))));
+        assert_eq!(error("(|+)"), Err((DanglingQuantifier, span(2, 3))));
+        assert_eq!(error("(a|b)"), Ok(()));
+        assert_eq!(error("a\\$"), Ok(()));
+        assert!(Regex::new("a\\$").unwrap().is_match("a$"));
+        assert_eq!(error("\\t\\n\\r\\f\\v\\0"), Ok(()));
+        assert!(Regex::new("a\\tb").unwrap().is_match("x a\tb"));
+
+        let err = crate::Error::from(Regex::new("ab)").unwrap_err());
+        assert_eq!(
+            err.to_string(),
+            "parse -- unmatched closing parenthesis at byte 2"
+        );
+    }
+
+    #[test]
+    fn it_reports_every_error_at_once() {
+        use ParseErrorKind::*;
+
+        let errors = |expr| {
+            Regex::diagnose(expr)
+                .into_iter()
+                .map(|e| (e.kind, e.span.start))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors("^*a)b|(c"),
+            [
+                (DanglingQuantifier, 1),
+                (UnopenedGroup, 3),
+                (BareAlternation, 5),
+                (UnclosedGroup, 6),
+            ]
+        );
+        // An unclosed bracket ends at the `)` of its group.
+        assert_eq!(
+            errors("(a[bc)d]+"),
+            [(UnclosedBracket, 2), (UnopenedBracket, 7)]
+        );
+        assert_eq!(errors("[(a]b)"), [(UnclosedGroup, 1), (UnopenedGroup, 5)]);
+        assert_eq!(
+            errors("(a|[b|c])\\"),
+            [(BareAlternation, 5), (BadEscape, 9)]
+        );
+        assert!(errors("(a|[bc])+$").is_empty());
+        assert_eq!(Regex::new("^*a)b|(c").unwrap_err().kind, DanglingQuantifier);
+    }
+
+    #[test]
+    fn it_shares_regexes_between_threads() {
+        fn assert_owned<T: Clone + Send + Sync + 'static>() {}
+        assert_owned::<Regex>();
+        assert_owned::<bytes::Regex>();
+        assert_owned::<RegexSet>();
+        assert_owned::<bytes::RegexSet>();
+
+        let regexes: HashMap<&str, Regex> = ["\\d+$", "(cat|dog)s", "\\w+ \\w+"]
+            .into_iter()
+            .map(|expr| (expr, Regex::new(expr).unwrap()))
+            .collect();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        assert!(regexes["\\d+$"].is_match("agent 007"));
+                        assert!(regexes["(cat|dog)s"].clone().is_match("hot dogs"));
+                        assert!(!regexes["\\w+ \\w+"].is_match("single"));
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn it_displays_the_canonical_expression() {
+        let r = Regex::new("^(?<n>\\d+)\\.\\xff[$]$").unwrap();
+        assert_eq!(r.to_string(), "^(?P<n>\\d+)\\.\\xFF[\\$]$");
+        assert_eq!(
+            Regex::new(&r.to_string()).unwrap().to_string(),
+            r.to_string()
+        );
+    }
 
     #[test]
     fn it_matches_literals() {
-        let r = Regex::new("a");
+        let r = Regex::new("a").unwrap();
         assert!(r.is_match("abc"));
         assert!(r.is_match("123abc"));
         assert!(!r.is_match("xyz"));
@@ -80,57 +647,57 @@ mod tests {
 
     #[test]
     fn it_matches_digits() {
-        let r = Regex::new("\\d");
+        let r = Regex::new("\\d").unwrap();
         assert!(r.is_match("apple123"));
         assert!(!r.is_match("xyz"));
     }
 
     #[test]
     fn it_matches_alphanumeric_characters() {
-        let r = Regex::new("\\w");
+        let r = Regex::new("\\w").unwrap();
         assert!(r.is_match("alpha-num3ric"));
         assert!(!r.is_match("$!?"));
     }
 
     #[test]
     fn it_matches_wildcard() {
-        let r = Regex::new("d.g");
+        let r = Regex::new("d.g").unwrap();
         assert!(r.is_match("dog"));
         assert!(r.is_match("dig"));
         assert!(!r.is_match("cog"));
 
-        let r = Regex::new("g.+");
+        let r = Regex::new("g.+").unwrap();
         assert!(r.is_match("goøö0Ogol"));
 
-        let r = Regex::new("g.+gol");
+        let r = Regex::new("g.+gol").unwrap();
         assert!(r.is_match("goøö0Ogol"));
     }
 
     #[test]
     fn it_matches_positive_character_group() {
-        let r = Regex::new("[abc]");
+        let r = Regex::new("[abc]").unwrap();
         assert!(r.is_match("apple"));
         assert!(!r.is_match("dog"));
     }
 
     #[test]
     fn it_matches_negative_character_group() {
-        let r = Regex::new("[^abc]");
+        let r = Regex::new("[^abc]").unwrap();
         assert!(r.is_match("dog"));
         assert!(!r.is_match("cab"));
     }
 
     #[test]
     fn it_matches_combining_character_classes() {
-        let r = Regex::new("\\d apple");
+        let r = Regex::new("\\d apple").unwrap();
         assert!(r.is_match("1 apple"));
         assert!(!r.is_match("1 orange"));
 
-        let r = Regex::new("\\d\\d\\d apple");
+        let r = Regex::new("\\d\\d\\d apple").unwrap();
         assert!(r.is_match("100 apple"));
         assert!(!r.is_match("1 apple"));
 
-        let r = Regex::new("\\d \\w\\w\\ws");
+        let r = Regex::new("\\d \\w\\w\\ws").unwrap();
         assert!(r.is_match("3 dogs"));
         assert!(r.is_match("4 cats"));
         assert!(!r.is_match("1 dog"));
@@ -138,21 +705,21 @@ mod tests {
 
     #[test]
     fn it_matches_with_start_anchor() {
-        let r = Regex::new("^log");
+        let r = Regex::new("^log").unwrap();
         assert!(r.is_match("logs"));
         assert!(!r.is_match("slog"));
     }
 
     #[test]
     fn it_matches_with_end_anchor() {
-        let r = Regex::new("dog$");
+        let r = Regex::new("dog$").unwrap();
         assert!(r.is_match("dog"));
         assert!(!r.is_match("dogs"));
     }
 
     #[test]
     fn it_matches_zero_or_one_times() {
-        let r = Regex::new("dogs?");
+        let r = Regex::new("dogs?").unwrap();
         assert!(r.is_match("dog"));
         assert!(r.is_match("dogs"));
         assert!(!r.is_match("cat"));
@@ -160,9 +727,156 @@ mod tests {
 
     #[test]
     fn it_matches_alternation() {
-        let r = Regex::new("(dog|cat)");
+        let r = Regex::new("(dog|cat)").unwrap();
         assert!(r.is_match("dog"));
         assert!(r.is_match("cat"));
         assert!(!r.is_match("dig"));
     }
+
+    #[test]
+    fn it_matches_pure_literals() {
+        let r = Regex::new("needle").unwrap();
+        assert!(matches!(r.strategy, Strategy::Literal(_)));
+        assert!(r.is_match("a needle in a haystack"));
+        assert!(!r.is_match("a needl"));
+
+        assert!(Regex::new("^needle$").unwrap().is_match("needle"));
+        assert!(!Regex::new("^needle$").unwrap().is_match("needles"));
+        assert!(Regex::new("^$").unwrap().is_match(""));
+        assert!(!Regex::new("^$").unwrap().is_match("x"));
+    }
+
+    #[test]
+    fn it_matches_literal_alternations() {
+        let r = Regex::new("(GET|POST|PUT|DELETE|PATCH)").unwrap();
+        assert!(matches!(r.strategy, Strategy::Literals(_)));
+        assert!(r.is_match("> PATCH /users"));
+        assert!(!r.is_match("> HEAD /users"));
+
+        let r = Regex::new("(GET|POST) /\\w+").unwrap();
+        assert!(r.is_match("xx POST /users"));
+        assert!(!r.is_match("xx POST / GET"));
+    }
+
+    #[test]
+    fn it_matches_literal_alternations_with_shared_prefixes() {
+        for expr in ["(PUT|POST|PATCH)", "(error|errno)", "(?:error|errno)"] {
+            let r = Regex::new(expr).unwrap();
+            assert!(matches!(r.strategy, Strategy::Literals(_)), "{expr}");
+        }
+
+        let r = Regex::new("(PUT|POST|PATCH)").unwrap();
+        assert_eq!(r.find("> PATCH /users").unwrap().text, "PATCH");
+        assert!(!r.is_match("> PING /users"));
+
+        let r = Regex::new("(error|errno)").unwrap();
+        assert_eq!(
+            r.captures("an errno 2").unwrap().get(1).unwrap().text,
+            "errno"
+        );
+    }
+
+    #[test]
+    fn it_keeps_captures_of_factored_alternations() {
+        let r = Regex::new("(abc|abd|ab)(d?)").unwrap();
+        let caps = r.captures("xabde").unwrap();
+        assert_eq!(caps.get(1).unwrap().text, "abd");
+        assert_eq!(caps.get(2).unwrap().text, "");
+
+        let caps = r.captures("abe").unwrap();
+        assert_eq!(caps.get(1).unwrap().text, "ab");
+    }
+
+    #[test]
+    fn it_routes_short_patterns_to_shift_and() {
+        let r = Regex::new("\\d+(apple|orange)s?").unwrap();
+        assert!(matches!(r.strategy, Strategy::ShiftAnd(_)));
+        assert!(r.is_match("buy 3apples"));
+        assert!(!r.is_match("buy 3pears"));
+
+        let r = Regex::new(&"\\d".repeat(65)).unwrap();
+        assert!(matches!(r.strategy, Strategy::Automaton));
+        assert!(r.is_match(&"1".repeat(65)));
+    }
+
+    #[test]
+    fn it_matches_end_anchored_patterns_backwards() {
+        let r = Regex::new("\\d+ (apple|orange)s?$").unwrap();
+        assert!(matches!(r.strategy, Strategy::ReverseAnchored(_)));
+        assert!(r.is_match("buy 3 apples"));
+        assert!(r.is_match("3 apples or 12 orange"));
+        assert!(!r.is_match("buy 3 apples!"));
+
+        let r = Regex::new("^\\d+ apples$").unwrap();
+        assert!(matches!(r.strategy, Strategy::ShiftAnd(_)));
+    }
+
+    #[test]
+    fn it_matches_suffix_literal_patterns_backwards() {
+        let r = Regex::new("\\w+\\.log").unwrap();
+        assert!(matches!(r.strategy, Strategy::ReverseSuffix(_)));
+        assert!(r.is_match("tail app.log -f"));
+        assert!(!r.is_match("tail .log -f"));
+
+        let r = Regex::new("x\\w+a").unwrap();
+        assert!(r.is_match(&format!("x{}", "a".repeat(1000))));
+        assert!(!r.is_match(&"a".repeat(1000)));
+    }
+
+    #[test]
+    fn it_finds_matches() {
+        let r = Regex::new("\\d+").unwrap();
+        let m = r.find("abc 123 456").unwrap();
+        assert_eq!((m.start, m.end, m.text), (4, 7, "123"));
+        assert!(r.find("abc").is_none());
+
+        let texts: Vec<&str> = r.find_iter("1 22 x333").map(|m| m.text).collect();
+        assert_eq!(texts, ["1", "22", "333"]);
+    }
+
+    #[test]
+    fn it_advances_past_empty_matches() {
+        let r = Regex::new("a*").unwrap();
+        let spans: Vec<(usize, usize)> = r.find_iter("baaé").map(|m| (m.start, m.end)).collect();
+        assert_eq!(spans, [(0, 0), (1, 3), (5, 5)]);
+
+        // No empty match right after a match, but one a char further is kept.
+        let r = Regex::new("\\d*").unwrap();
+        let spans: Vec<(usize, usize)> = r.find_iter("12ab3").map(|m| (m.start, m.end)).collect();
+        assert_eq!(spans, [(0, 2), (3, 3), (4, 5)]);
+
+        assert_eq!(Regex::new("x*").unwrap().find_iter("").count(), 1);
+    }
+
+    #[test]
+    fn it_matches_with_required_literals() {
+        let r = Regex::new("\\d+ ERROR \\w+").unwrap();
+        assert!(r.is_match("at 12 ERROR disk"));
+        assert!(!r.is_match("at 12 WARN disk"));
+        assert!(!r.is_match("at ERROR disk"));
+
+        let r = Regex::new("GET /\\w+").unwrap();
+        assert!(r.is_match("x GET x GET /index"));
+        assert!(!r.is_match("x GET x GET /"));
+    }
+
+    #[test]
+    fn it_matches_inner_literal_patterns_both_ways() {
+        let r = Regex::new("\\d+ ERROR \\w+").unwrap();
+        assert!(matches!(r.strategy, Strategy::ReverseInner(_)));
+        assert!(r.is_match("at 12 ERROR disk"));
+        assert!(!r.is_match("at ERROR disk 12 ERROR"));
+    }
+
+    #[test]
+    fn it_compiles_to_a_dense_dfa() {
+        let dfa = Regex::new("^(cat|dog)s?$")
+            .unwrap()
+            .to_dense_dfa(1 << 16)
+            .unwrap();
+        assert!(dfa.is_match("cats"));
+        assert!(dfa.is_match("dog"));
+        assert!(!dfa.is_match("hotdog"));
+        assert!(!dfa.is_match("dogss"));
+    }
 }
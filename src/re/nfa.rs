@@ -0,0 +1,478 @@
+use super::budget::Budget;
+use super::charset::{Alphabet, CharSet, Unit};
+use super::hir::{group_names, Hir};
+use super::utf8;
+use crate::Result;
+
+pub type StateId = usize;
+
+#[derive(Debug, Clone)]
+pub enum State {
+    Char {
+        set: CharSet,
+        next: StateId,
+    },
+    Split(StateId, StateId),
+    /// Records the current position in a capture slot.
+    Save {
+        slot: usize,
+        next: StateId,
+    },
+    /// Accepts a match of the pattern with the given index.
+    Match(usize),
+}
+
+impl State {
+    /// Whether the state is left without consuming a char.
+    pub fn is_epsilon(&self) -> bool {
+        matches!(self, Self::Split(..) | Self::Save { .. })
+    }
+}
+
+/// A Thompson NFA compiled from the HIR of parsed patterns.
+#[derive(Debug, Clone)]
+pub struct Nfa {
+    states: Vec<State>,
+    start: StateId,
+    start_anchor: bool,
+    end_anchor: bool,
+    reverse: bool,
+    alphabet: Alphabet,
+}
+
+impl Nfa {
+    pub fn new(patterns: &[Hir], start_anchor: bool, end_anchor: bool) -> Self {
+        Self::build(patterns, start_anchor, end_anchor, false)
+    }
+
+    /// Compiles an NFA that reads the haystack from its end towards its start
+    /// and matches the patterns backwards. The anchors are those of the
+    /// patterns, so `end_anchor` anchors the reverse search where it begins.
+    pub fn reverse(patterns: &[Hir], start_anchor: bool, end_anchor: bool) -> Self {
+        Self::build(patterns, end_anchor, start_anchor, true)
+    }
+
+    fn build(patterns: &[Hir], start_anchor: bool, end_anchor: bool, reverse: bool) -> Self {
+        let mut compiler = Compiler {
+            states: vec![State::Match(0)],
+            reverse,
+        };
+        let start = compiler.concat(patterns, 0, 0);
+
+        Self::from_states(compiler.states, start, start_anchor, end_anchor, reverse)
+    }
+
+    /// Compiles several patterns into one NFA in which each pattern ends in
+    /// its own `Match` state. Returns the NFA with the start state of every
+    /// pattern. The NFA itself starts at all of them and has no anchors.
+    pub fn union(patterns: &[Vec<Hir>]) -> (Self, Vec<StateId>) {
+        let mut compiler = Compiler {
+            states: vec![],
+            reverse: false,
+        };
+
+        let starts: Vec<StateId> = patterns
+            .iter()
+            .enumerate()
+            .map(|(i, patterns)| {
+                let matched = compiler.push(State::Match(i));
+                compiler.concat(patterns, matched, 0)
+            })
+            .collect();
+
+        let start = starts
+            .iter()
+            .rev()
+            .copied()
+            .reduce(|rest, start| compiler.push(State::Split(start, rest)))
+            .unwrap_or_else(|| {
+                compiler.push(State::Char {
+                    set: CharSet::empty(),
+                    next: 0,
+                })
+            });
+
+        let nfa = Self::from_states(compiler.states, start, false, false, false);
+        (nfa, starts)
+    }
+
+    fn from_states(
+        states: Vec<State>,
+        start: StateId,
+        start_anchor: bool,
+        end_anchor: bool,
+        reverse: bool,
+    ) -> Self {
+        let alphabet = Alphabet::new(states.iter().filter_map(|state| match state {
+            State::Char { set, .. } => Some(set),
+            _ => None,
+        }));
+
+        Self {
+            states,
+            start,
+            start_anchor,
+            end_anchor,
+            reverse,
+            alphabet,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn state(&self, id: StateId) -> &State {
+        &self.states[id]
+    }
+
+    pub fn start(&self) -> StateId {
+        self.start
+    }
+
+    pub fn start_anchor(&self) -> bool {
+        self.start_anchor
+    }
+
+    pub fn end_anchor(&self) -> bool {
+        self.end_anchor
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    /// Decodes the next unit to read once `pos` bytes of `hay` have been read,
+    /// from whichever end of `hay` the NFA starts at.
+    pub fn next_unit(&self, hay: &[u8], pos: usize) -> Option<(Unit, usize)> {
+        if self.reverse {
+            utf8::decode_last(&hay[..hay.len() - pos])
+        } else {
+            utf8::decode(&hay[pos..])
+        }
+    }
+
+    /// Adds `id` and every state reachable from it without consuming a char.
+    pub fn add_closure(&self, set: &mut SparseSet, id: StateId, stack: &mut Vec<StateId>) {
+        stack.push(id);
+
+        while let Some(id) = stack.pop() {
+            if !set.insert(id) {
+                continue;
+            }
+
+            match self.states[id] {
+                State::Split(a, b) => {
+                    // Push the preferred branch last so that it is visited first.
+                    stack.push(b);
+                    stack.push(a);
+                }
+                State::Save { next, .. } => stack.push(next),
+                _ => {}
+            }
+        }
+    }
+
+    /// Follows every `Char` state in `cur` accepting `unit` into `next`.
+    pub fn step(
+        &self,
+        cur: &SparseSet,
+        unit: Unit,
+        next: &mut SparseSet,
+        stack: &mut Vec<StateId>,
+    ) {
+        for id in cur.iter() {
+            if let State::Char { set, next: to } = &self.states[id] {
+                if set.contains(unit) {
+                    self.add_closure(next, *to, stack);
+                }
+            }
+        }
+    }
+
+    pub fn has_match(&self, set: &SparseSet) -> bool {
+        set.iter()
+            .any(|id| matches!(self.states[id], State::Match(_)))
+    }
+
+    /// Simulates the NFA over `hay` tracking every active state at once.
+    /// Advancing each active state by one unit costs a step of `budget`.
+    pub fn is_match(&self, hay: &[u8], budget: &mut Budget) -> Result<bool> {
+        let mut cur = SparseSet::new(self.len());
+        let mut next = SparseSet::new(self.len());
+        let mut stack = vec![];
+        let mut pos = 0;
+
+        self.add_closure(&mut cur, self.start, &mut stack);
+
+        loop {
+            if self.has_match(&cur) && (!self.end_anchor || pos == hay.len()) {
+                return Ok(true);
+            }
+
+            let Some((unit, len)) = self.next_unit(hay, pos) else {
+                return Ok(false);
+            };
+
+            budget.spend(cur.len() as u64)?;
+
+            next.clear();
+            self.step(&cur, unit, &mut next, &mut stack);
+
+            if !self.start_anchor {
+                self.add_closure(&mut next, self.start, &mut stack);
+            }
+
+            if next.is_empty() {
+                return Ok(false);
+            }
+
+            std::mem::swap(&mut cur, &mut next);
+            pos += len;
+        }
+    }
+}
+
+struct Compiler {
+    states: Vec<State>,
+    reverse: bool,
+}
+
+impl Compiler {
+    fn push(&mut self, state: State) -> StateId {
+        self.states.push(state);
+        self.states.len() - 1
+    }
+
+    // Patterns are compiled back to front so that the state following each
+    // pattern already exists when the pattern itself is compiled. Compiling
+    // them front to back instead yields the reversed NFA.
+    //
+    // `group` is the index of the first capturing group in `patterns`, counted
+    // from 0 for the group numbered 1.
+    fn concat(&mut self, patterns: &[Hir], next: StateId, group: usize) -> StateId {
+        let groups: Vec<usize> = patterns
+            .iter()
+            .scan(group, |group, pat| {
+                let first = *group;
+                *group += group_count(pat);
+                Some(first)
+            })
+            .collect();
+        let compiled = patterns.iter().zip(groups);
+
+        if self.reverse {
+            compiled.fold(next, |next, (pat, group)| self.compile(pat, next, group))
+        } else {
+            compiled
+                .rev()
+                .fold(next, |next, (pat, group)| self.compile(pat, next, group))
+        }
+    }
+
+    fn compile(&mut self, pat: &Hir, next: StateId, group: usize) -> StateId {
+        match pat {
+            Hir::Literal(lit) => {
+                let chars = lit.chars().map(CharSet::single);
+
+                if self.reverse {
+                    chars.fold(next, |next, set| self.push(State::Char { set, next }))
+                } else {
+                    chars
+                        .rev()
+                        .fold(next, |next, set| self.push(State::Char { set, next }))
+                }
+            }
+            Hir::Class(set) => self.push(State::Char {
+                set: set.clone(),
+                next,
+            }),
+            Hir::Star(p) => {
+                let split = self.push(State::Split(next, next));
+                let body = self.compile(p, split, group);
+                self.states[split] = State::Split(body, next);
+                split
+            }
+            Hir::Plus(p) => {
+                let split = self.push(State::Split(next, next));
+                let body = self.compile(p, split, group);
+                self.states[split] = State::Split(body, next);
+                body
+            }
+            Hir::Optional(p) => {
+                let body = self.compile(p, next, group);
+                self.push(State::Split(body, next))
+            }
+            Hir::Capture { branches, .. } => {
+                // The reversed NFA leaves the group where it starts.
+                let (enter, leave) = if self.reverse {
+                    (2 * group + 1, 2 * group)
+                } else {
+                    (2 * group, 2 * group + 1)
+                };
+
+                let next = self.push(State::Save { slot: leave, next });
+                let entry = self.alternate(branches, next, group + 1);
+                self.push(State::Save {
+                    slot: enter,
+                    next: entry,
+                })
+            }
+            Hir::Group(branches) => self.alternate(branches, next, group),
+        }
+    }
+
+    fn alternate(&mut self, branches: &[Vec<Hir>], next: StateId, group: usize) -> StateId {
+        let mut group = group;
+        let entries: Vec<StateId> = branches
+            .iter()
+            .map(|branch| {
+                let entry = self.concat(branch, next, group);
+                group += branch.iter().map(group_count).sum::<usize>();
+                entry
+            })
+            .collect();
+
+        entries
+            .into_iter()
+            .rev()
+            .reduce(|rest, entry| self.push(State::Split(entry, rest)))
+            .unwrap_or(next)
+    }
+}
+
+// The number of capturing groups in `pat`, including itself.
+fn group_count(pat: &Hir) -> usize {
+    group_names(std::slice::from_ref(pat)).len() - 1
+}
+
+/// A set of NFA states that remembers insertion order and clears in O(1).
+#[derive(Debug, Clone)]
+pub struct SparseSet {
+    dense: Vec<StateId>,
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        }
+    }
+
+    pub fn insert(&mut self, id: StateId) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+
+        self.sparse[id] = self.dense.len();
+        self.dense.push(id);
+        true
+    }
+
+    pub fn contains(&self, id: StateId) -> bool {
+        self.dense.get(self.sparse[id]) == Some(&id)
+    }
+
+    pub fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = StateId> + '_ {
+        self.dense.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hir::translate;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    fn nfa(expr: &str) -> Nfa {
+        Nfa::new(&translate(&parse_pattern(expr).patterns()), false, false)
+    }
+
+    fn matches(n: &Nfa, hay: &[u8]) -> bool {
+        n.is_match(hay, &mut Budget::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn it_simulates_quantifiers() {
+        let n = nfa("ca*t");
+        assert!(matches(&n, b"ct"));
+        assert!(matches(&n, b"caaat"));
+        assert!(!matches(&n, b"cut"));
+
+        let n = nfa("a+a");
+        assert!(matches(&n, b"aa"));
+        assert!(!matches(&n, b"a"));
+    }
+
+    #[test]
+    fn it_simulates_alternations() {
+        let n = nfa("(cat|dog)s");
+        assert!(matches(&n, b"hotdogs"));
+        assert!(!matches(&n, b"dog"));
+
+        let n = nfa("a(b|)c");
+        assert!(matches(&n, b"ac"));
+        assert!(matches(&n, b"abc"));
+    }
+
+    #[test]
+    fn it_respects_anchors() {
+        let patterns = translate(&parse_pattern("ab").patterns());
+
+        let n = Nfa::new(&patterns, true, false);
+        assert!(matches(&n, b"abc"));
+        assert!(!matches(&n, b"cab"));
+
+        let n = Nfa::new(&patterns, false, true);
+        assert!(matches(&n, b"cab"));
+        assert!(!matches(&n, b"abc"));
+    }
+
+    #[test]
+    fn it_matches_backwards() {
+        let patterns = translate(&parse_pattern("a(bc|d)+e?").patterns());
+
+        let n = Nfa::reverse(&patterns, false, true);
+        assert!(matches(&n, b"xxabcde"));
+        assert!(matches(&n, "é abc".as_bytes()));
+        assert!(!matches(&n, b"abcdex"));
+        assert!(!matches(&n, b"bcd"));
+
+        let n = Nfa::reverse(&patterns, true, true);
+        assert!(matches(&n, b"adbc"));
+        assert!(!matches(&n, b"xadbc"));
+    }
+
+    #[test]
+    fn it_stops_when_the_budget_runs_out() {
+        use super::super::budget::Limits;
+        use crate::Error;
+
+        let n = nfa("(a|aa)+b");
+        let hay = "a".repeat(100);
+        let mut budget = Budget::new(Limits {
+            step_limit: Some(50),
+            timeout: None,
+        });
+
+        assert!(matches!(
+            n.is_match(hay.as_bytes(), &mut budget),
+            Err(Error::StepLimit(50))
+        ));
+        assert!(!matches(&n, hay.as_bytes()));
+    }
+}
@@ -1,154 +1,226 @@
+use super::charset::CharSet;
 use super::Letters;
+use std::fmt;
+use thiserror::Error as ThisError;
+
+/// The byte range of a node in the parsed expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A node of the parsed expression, with the span of the text it was parsed
+/// from.
+#[derive(Debug)]
+pub struct Pattern<'a> {
+    pub kind: PatternKind<'a>,
+    pub span: Span,
+}
+
+/// Patterns are equal when they have the same structure, wherever in an
+/// expression they were parsed from.
+impl PartialEq for Pattern<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
 
 #[derive(Debug, PartialEq)]
-pub enum Pattern<'a> {
+pub enum PatternKind<'a> {
     Lit(&'a str),
+    /// A `\xHH` escape. At 0x80 and above, it matches the byte where it is
+    /// not part of a valid UTF-8 sequence, and otherwise the char U+00HH.
+    Byte(u8),
     AlphaNumeric,
     Digit,
+    Whitespace,
     Wildcard,
+    /// The chars from the first to the second one, such as `a-z` in brackets.
+    Range(char, char),
     PGroup(Vec<Pattern<'a>>),
     NGroup(Vec<Pattern<'a>>),
     MoreThanZero(Box<Pattern<'a>>),
     MoreThanOne(Box<Pattern<'a>>),
     ZeroOrOne(Box<Pattern<'a>>),
     Alternation(Vec<Vec<Pattern<'a>>>),
+    NonCapturing(Vec<Vec<Pattern<'a>>>),
+    Named(&'a str, Vec<Vec<Pattern<'a>>>),
 }
 
-impl Pattern<'_> {
-    pub fn search_match_pos(&self, s: &str) -> Option<usize> {
-        let mut pos = 0;
-        let mut letters = Letters::new(s);
-
-        while self.match_size(letters.tail()).is_none() {
-            let l = letters.next()?;
-            pos += l.len();
-
-            if pos >= s.len() {
-                return None;
-            }
+impl<'a> Pattern<'a> {
+    /// Returns the branches of a group, whether it captures or not.
+    pub fn branches(&self) -> Option<&[Vec<Pattern<'a>>]> {
+        match &self.kind {
+            PatternKind::Alternation(branches)
+            | PatternKind::NonCapturing(branches)
+            | PatternKind::Named(_, branches) => Some(branches),
+            _ => None,
         }
-        Some(pos)
     }
 
-    pub fn evaluate_with_next(&self) -> bool {
-        matches!(
-            self,
-            Self::MoreThanOne(_) | Self::MoreThanZero(_) | Self::ZeroOrOne(_)
-        )
+    /// Returns the set of chars a single-char pattern accepts, or `None` if the
+    /// pattern is a quantifier or an alternation.
+    pub fn char_set(&self) -> Option<CharSet> {
+        match &self.kind {
+            PatternKind::Lit(lit) => lit.chars().next().map(CharSet::single),
+            PatternKind::Byte(b) => Some(CharSet::byte(*b)),
+            PatternKind::AlphaNumeric => Some(CharSet::word()),
+            PatternKind::Digit => Some(CharSet::digit()),
+            PatternKind::Whitespace => Some(CharSet::whitespace()),
+            PatternKind::Wildcard => Some(CharSet::any()),
+            PatternKind::Range(lo, hi) => Some(CharSet::from_chars([(*lo, *hi)])),
+            PatternKind::PGroup(pats) => Some(group_char_set(pats)),
+            PatternKind::NGroup(pats) => Some(group_char_set(pats).negate()),
+            PatternKind::MoreThanZero(_)
+            | PatternKind::MoreThanOne(_)
+            | PatternKind::ZeroOrOne(_)
+            | PatternKind::Alternation(_)
+            | PatternKind::NonCapturing(_)
+            | PatternKind::Named(..) => None,
+        }
     }
+}
 
-    pub fn match_size(&self, s: &str) -> Option<usize> {
-        let mut letters = Letters::new(s);
-
-        match self {
-            Self::Lit(lit) => letters
-                .next()
-                .and_then(|l| if l == *lit { Some(l.len()) } else { None }),
-            Self::AlphaNumeric => letters.next().and_then(|l| {
-                if is_ascii_alphanumeric(l) {
-                    Some(l.len())
-                } else {
-                    None
-                }
-            }),
-            Self::Digit => letters.next().and_then(|l| {
-                if is_ascii_digit(l) {
-                    Some(l.len())
-                } else {
-                    None
-                }
-            }),
-            Self::Wildcard => letters.next().map(|l| l.len()),
-            Self::PGroup(pats) => pats.iter().filter_map(|pat| pat.match_size(s)).next(),
-            Self::NGroup(pats) => {
-                if pats.iter().all(|pat| pat.match_size(s).is_none()) {
-                    // FIXME:
-                    // This is wroing.
-                    Some(1)
-                } else {
-                    None
-                }
+/// Writes the pattern back as canonical regex text, escaping every
+/// metacharacter, so that it parses to an equal pattern.
+impl fmt::Display for Pattern<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PatternKind::Lit(lit) if is_meta(lit) => write!(f, "\\{lit}"),
+            PatternKind::Lit(lit) => match CONTROL_ESCAPES.iter().find(|&&(_, c)| c == *lit) {
+                Some((letter, _)) => write!(f, "\\{letter}"),
+                None => f.write_str(lit),
+            },
+            PatternKind::Byte(b) => write!(f, "\\x{b:02X}"),
+            PatternKind::AlphaNumeric => f.write_str("\\w"),
+            PatternKind::Digit => f.write_str("\\d"),
+            PatternKind::Whitespace => f.write_str("\\s"),
+            PatternKind::Wildcard => f.write_str("."),
+            PatternKind::Range(lo, hi) => {
+                write_char(f, *lo)?;
+                f.write_str("-")?;
+                write_char(f, *hi)
             }
-            Self::MoreThanZero(pat) => {
-                let mut acc = 0;
+            PatternKind::PGroup(pats) => write!(f, "[{}]", Concat(pats)),
+            PatternKind::NGroup(pats) => write!(f, "[^{}]", Concat(pats)),
+            PatternKind::MoreThanZero(p) => write!(f, "{p}*"),
+            PatternKind::MoreThanOne(p) => write!(f, "{p}+"),
+            PatternKind::ZeroOrOne(p) => write!(f, "{p}?"),
+            PatternKind::Alternation(branches) => write!(f, "({})", Branches(branches)),
+            PatternKind::NonCapturing(branches) => write!(f, "(?:{})", Branches(branches)),
+            PatternKind::Named(name, branches) => write!(f, "(?P<{name}>{})", Branches(branches)),
+        }
+    }
+}
 
-                while let Some(size) = pat.match_size(&s[acc..]) {
-                    acc += size;
-                }
+fn is_meta(lit: &str) -> bool {
+    matches!(
+        lit,
+        "\\" | "." | "[" | "]" | "(" | ")" | "|" | "*" | "+" | "?" | "^" | "$" | "-"
+    )
+}
 
-                Some(acc)
-            }
-            Self::MoreThanOne(pat) => {
-                let mut acc = 0;
+// Writes a range end the way a literal of it would be written, using a `\xHH`
+// escape for control chars.
+fn write_char(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    let mut buf = [0; 4];
 
-                if let Some(size) = pat.match_size(s) {
-                    acc += size;
-                } else {
-                    return None;
-                }
+    match c.encode_utf8(&mut buf) {
+        lit if is_meta(lit) => write!(f, "\\{lit}"),
+        _ if c.is_ascii_control() => write!(f, "\\x{:02X}", c as u8),
+        lit => f.write_str(lit),
+    }
+}
 
-                while let Some(size) = pat.match_size(&s[acc..]) {
-                    acc += size;
-                }
+/// Displays patterns one after the other.
+pub struct Concat<'p, 'a>(pub &'p [Pattern<'a>]);
 
-                Some(acc)
-            }
-            Self::ZeroOrOne(pat) => {
-                let size = pat.match_size(s).unwrap_or(0);
-                Some(size)
-            }
-            Self::Alternation(pats) => pats
-                .iter()
-                .find_map(|patterns| search_match_size(patterns, s)),
-        }
+impl fmt::Display for Concat<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|pat| write!(f, "{pat}"))
     }
 }
 
-pub fn search_match_size(patterns: &[Pattern], s: &str) -> Option<usize> {
-    let mut cur_pos: usize = 0;
-    let mut prev_pat: Option<&Pattern> = None;
-
-    for pat in patterns {
-        if cur_pos > s.len() {
-            return None;
-        }
+struct Branches<'p, 'a>(&'p [Vec<Pattern<'a>>]);
 
-        if pat.evaluate_with_next() {
-            prev_pat = Some(pat);
-            continue;
-        }
+impl fmt::Display for Branches<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, branch) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("|")?;
+            }
 
-        if let Some(prev) = prev_pat.take() {
-            let size = pat
-                .search_match_pos(&s[cur_pos..])
-                .and_then(|b| prev.match_size(&s[cur_pos..(cur_pos + b)]))?;
-            cur_pos += size;
+            write!(f, "{}", Concat(branch))?;
         }
 
-        let size = pat.match_size(&s[cur_pos..])?;
-        cur_pos += size;
-    }
-
-    if let Some(pat) = prev_pat.take() {
-        let size = pat.match_size(&s[cur_pos..])?;
-        cur_pos += size;
+        Ok(())
     }
+}
 
-    Some(cur_pos)
+// Members of a bracket group are all single chars, but quantifier and
+// alternation characters inside brackets still go through the regular parser,
+// so fall back to the chars they wrap.
+fn group_char_set(pats: &[Pattern]) -> CharSet {
+    pats.iter().fold(CharSet::empty(), |acc, pat| {
+        let set = match &pat.kind {
+            PatternKind::MoreThanZero(p)
+            | PatternKind::MoreThanOne(p)
+            | PatternKind::ZeroOrOne(p) => group_char_set(std::slice::from_ref(p.as_ref())),
+            _ => match pat.branches() {
+                Some(branches) => branches
+                    .iter()
+                    .fold(CharSet::empty(), |acc, b| acc.union(&group_char_set(b))),
+                None => pat.char_set().unwrap_or_else(CharSet::empty),
+            },
+        };
+        acc.union(&set)
+    })
 }
 
 #[derive(Debug)]
 enum PatternChar<'a> {
-    Itself(Pattern<'a>),
+    Itself(PatternKind<'a>),
     MoreThanZero,
     MoreThanOne,
     ZeroOrOne,
     PGroupOpen,
     NGroupOpen,
     GroupClose,
-    AltOpen,
+    AltOpen(GroupKind<'a>),
     AltClose,
     AltDelimiter,
+    BadEscape,
+}
+
+#[derive(Debug)]
+enum GroupKind<'a> {
+    Capturing,
+    NonCapturing,
+    Named(&'a str),
+}
+
+impl<'a> GroupKind<'a> {
+    // Reads the `?:`, `?P<name>` or `?<name>` right after an opening
+    // parenthesis and returns the kind of group with the rest of `expr`.
+    fn pick(expr: &'a str) -> (Self, &'a str) {
+        if let Some(rest) = expr.strip_prefix("?:") {
+            return (Self::NonCapturing, rest);
+        }
+
+        let named = expr
+            .strip_prefix("?P<")
+            .or_else(|| expr.strip_prefix("?<"))
+            .and_then(|rest| rest.split_once('>'))
+            .filter(|(name, _)| {
+                !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            });
+
+        match named {
+            Some((name, rest)) => (Self::Named(name), rest),
+            None => (Self::Capturing, expr),
+        }
+    }
 }
 
 impl<'a> PatternChar<'a> {
@@ -156,22 +228,37 @@ impl<'a> PatternChar<'a> {
         let mut letters = Letters::new(expr);
 
         match letters.next()? {
-            "\\" => match letters.next()? {
+            "\\" => match letters.next().unwrap_or_default() {
                 "w" => {
-                    let pat = Pattern::AlphaNumeric;
+                    let pat = PatternKind::AlphaNumeric;
                     Some((PatternChar::Itself(pat), letters.tail()))
                 }
                 "d" => {
-                    let pat = Pattern::Digit;
+                    let pat = PatternKind::Digit;
                     Some((PatternChar::Itself(pat), letters.tail()))
                 }
-                l => {
-                    let pat = Pattern::Lit(l);
+                "s" => {
+                    let pat = PatternKind::Whitespace;
                     Some((PatternChar::Itself(pat), letters.tail()))
                 }
+                l if control_char(l).is_some() => {
+                    let pat = PatternKind::Lit(control_char(l)?);
+                    Some((PatternChar::Itself(pat), letters.tail()))
+                }
+                "x" if parse_hex_byte(letters.tail()).is_some() => {
+                    let pat = PatternKind::Byte(parse_hex_byte(letters.tail())?);
+                    Some((PatternChar::Itself(pat), &letters.tail()[2..]))
+                }
+                // Any ASCII punctuation may be escaped, whether it is a
+                // metacharacter or not.
+                l if l.len() == 1 && l.as_bytes()[0].is_ascii_punctuation() => {
+                    let pat = PatternKind::Lit(l);
+                    Some((PatternChar::Itself(pat), letters.tail()))
+                }
+                _ => Some((PatternChar::BadEscape, letters.tail())),
             },
             "." => {
-                let pat = Pattern::Wildcard;
+                let pat = PatternKind::Wildcard;
                 Some((PatternChar::Itself(pat), letters.tail()))
             }
             "[" => {
@@ -186,17 +273,120 @@ impl<'a> PatternChar<'a> {
             "+" => Some((PatternChar::MoreThanOne, letters.tail())),
             "*" => Some((PatternChar::MoreThanZero, letters.tail())),
             "?" => Some((PatternChar::ZeroOrOne, letters.tail())),
-            "(" => Some((PatternChar::AltOpen, letters.tail())),
+            "(" => {
+                let (kind, rest) = GroupKind::pick(letters.tail());
+                Some((PatternChar::AltOpen(kind), rest))
+            }
             ")" => Some((PatternChar::AltClose, letters.tail())),
             "|" => Some((PatternChar::AltDelimiter, letters.tail())),
             l => {
-                let pat = Pattern::Lit(l);
+                let pat = PatternKind::Lit(l);
                 Some((PatternChar::Itself(pat), letters.tail()))
             }
         }
     }
 }
 
+// The escape letters of control chars, with the chars they stand for.
+const CONTROL_ESCAPES: [(&str, &str); 6] = [
+    ("t", "\t"),
+    ("n", "\n"),
+    ("r", "\r"),
+    ("f", "\x0C"),
+    ("v", "\x0B"),
+    ("0", "\0"),
+];
+
+fn control_char(letter: &str) -> Option<&'static str> {
+    CONTROL_ESCAPES
+        .iter()
+        .find(|&&(l, _)| l == letter)
+        .map(|&(_, c)| c)
+}
+
+// Two hex digits following `\x`.
+fn parse_hex_byte(expr: &str) -> Option<u8> {
+    let digits = expr.get(..2)?;
+
+    if digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        u8::from_str_radix(digits, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// What is wrong with an expression that cannot be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A `(` without its `)`.
+    UnclosedGroup,
+    /// A `)` without its `(`.
+    UnopenedGroup,
+    /// A `[` without its `]`.
+    UnclosedBracket,
+    /// A `]` without its `[`.
+    UnopenedBracket,
+    /// A `*`, `+` or `?` with nothing before it to repeat.
+    DanglingQuantifier,
+    /// A `|` that is not inside a group.
+    BareAlternation,
+    /// A `\` followed by nothing, or by a char it does not escape.
+    BadEscape,
+    /// A range in brackets that does not go from a char to a later one.
+    BadRange,
+    /// A group named like an earlier one.
+    DuplicateName,
+}
+
+impl ParseErrorKind {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::UnclosedGroup => "unclosed group",
+            Self::UnopenedGroup => "unmatched closing parenthesis",
+            Self::UnclosedBracket => "unclosed bracket",
+            Self::UnopenedBracket => "unmatched closing bracket",
+            Self::DanglingQuantifier => "quantifier has nothing to repeat",
+            Self::BareAlternation => "alternation is only supported inside a group",
+            Self::BadEscape => "invalid escape",
+            Self::BadRange => "invalid range",
+            Self::DuplicateName => "duplicate group name",
+        }
+    }
+
+    /// A short explanation to show next to the offending part.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::UnclosedGroup => "this group is never closed",
+            Self::UnopenedGroup => "no group to close here",
+            Self::UnclosedBracket => "this bracket is never closed",
+            Self::UnopenedBracket => "no bracket to close here",
+            Self::DanglingQuantifier => "nothing before this to repeat",
+            Self::BareAlternation => "put the alternatives in a group, as in `(a|b)`",
+            Self::BadEscape => "not a supported escape",
+            Self::BadRange => "the range must go from a char to a later one",
+            Self::DuplicateName => "an earlier group has this name",
+        }
+    }
+}
+
+/// An expression that cannot be parsed, with the span of the offending part
+/// of it. For an unclosed group or bracket, that is the opening one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ThisError)]
+#[error("{} at byte {}", kind.message(), span.start)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, start: usize, end: usize) -> Self {
+        Self {
+            kind,
+            span: Span { start, end },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParsedPatterns<'a> {
     inner: Vec<Pattern<'a>>,
@@ -208,95 +398,254 @@ impl<'a> ParsedPatterns<'a> {
     pub fn patterns(self) -> Vec<Pattern<'a>> {
         self.inner
     }
+}
+
+// What the parser is directly inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Nesting {
+    Top,
+    Group,
+    Bracket,
+}
 
-    pub fn completed(&self) -> bool {
-        self.remaining.is_empty()
+// Where a nested call of the parser is, which decides the closing characters
+// it stops at.
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    inside: Nesting,
+    // Whether an enclosing group or bracket is open. Its closing character
+    // also ends whatever is nested in it and was left unclosed.
+    groups: bool,
+    brackets: bool,
+}
+
+impl Context {
+    const TOP: Self = Self {
+        inside: Nesting::Top,
+        groups: false,
+        brackets: false,
+    };
+
+    fn enter(self, inside: Nesting) -> Self {
+        Self {
+            inside,
+            groups: self.groups || inside == Nesting::Group,
+            brackets: self.brackets || inside == Nesting::Bracket,
+        }
     }
 }
 
+/// Parses `expr`, recording the span of every node as a byte range of `expr`.
+///
+/// Panics if `expr` is not a valid pattern.
+#[cfg(test)]
 pub fn parse_pattern<'a>(expr: &'a str) -> ParsedPatterns<'a> {
+    let mut errors = vec![];
+    let parsed = parse(expr, expr.len(), Context::TOP, &mut errors, &mut vec![]);
+
+    if let Some(err) = errors.first() {
+        panic!("{err}");
+    }
+
+    parsed
+}
+
+/// Parses `expr` as the part of a larger expression starting at byte
+/// `offset`, so that spans are byte ranges of the larger expression.
+///
+/// The parser carries on after an error, so an invalid expression fails with
+/// every error found in it, in the order they appear in `expr`.
+pub fn parse_pattern_at(expr: &str, offset: usize) -> Result<Vec<Pattern<'_>>, Vec<ParseError>> {
+    let mut errors = vec![];
+    let parsed = parse(
+        expr,
+        offset + expr.len(),
+        Context::TOP,
+        &mut errors,
+        &mut vec![],
+    );
+
+    if errors.is_empty() {
+        Ok(parsed.patterns())
+    } else {
+        errors.sort_by_key(|err| err.span.start);
+        Err(errors)
+    }
+}
+
+// Every `rest` is a suffix of the top-level expression, which ends at byte
+// `end`, so its position is how much shorter it is.
+//
+// On an error, the parser pushes it to `errors` and skips the offending
+// character. A group or bracket left unclosed ends where an enclosing one
+// does, or at the end of the expression, and `last_char` is then `None`.
+fn parse<'a>(
+    expr: &'a str,
+    end: usize,
+    ctx: Context,
+    errors: &mut Vec<ParseError>,
+    // The names of the groups opened so far.
+    names: &mut Vec<&'a str>,
+) -> ParsedPatterns<'a> {
+    let pos = |s: &str| end - s.len();
     let mut rest_expr = expr;
     let mut patterns: Vec<Pattern<'a>> = vec![];
 
     while let Some((chr, mut rest)) = PatternChar::pick(rest_expr) {
+        let start = pos(rest_expr);
+        let error = |kind| ParseError::new(kind, start, pos(rest));
+
         match chr {
-            PatternChar::Itself(p) => {
-                patterns.push(p);
-            }
-            PatternChar::MoreThanZero => {
-                // TODO:
-                // handle when pop method returns None
-                if let Some(p) = patterns.pop() {
-                    patterns.push(Pattern::MoreThanZero(Box::new(p)));
-                }
+            PatternChar::Itself(kind) => {
+                let range = range_end(rest).filter(|_| ctx.inside == Nesting::Bracket);
+
+                let kind = match range {
+                    Some((hi, after)) => {
+                        rest = after;
+
+                        let hi = match hi {
+                            PatternChar::Itself(hi) => range_char(&hi),
+                            _ => None,
+                        };
+
+                        match (range_char(&kind), hi) {
+                            (Some(lo), Some(hi)) if lo <= hi => PatternKind::Range(lo, hi),
+                            _ => {
+                                let err =
+                                    ParseError::new(ParseErrorKind::BadRange, start, pos(rest));
+                                errors.push(err);
+                                rest_expr = rest;
+                                continue;
+                            }
+                        }
+                    }
+                    None => kind,
+                };
+
+                patterns.push(Pattern {
+                    kind,
+                    span: Span {
+                        start,
+                        end: pos(rest),
+                    },
+                });
             }
-            PatternChar::MoreThanOne => {
-                // TODO:
-                // handle when pop method returns None
-                if let Some(p) = patterns.pop() {
-                    patterns.push(Pattern::MoreThanOne(Box::new(p)));
-                }
+            PatternChar::BadEscape => errors.push(error(ParseErrorKind::BadEscape)),
+            PatternChar::MoreThanZero | PatternChar::MoreThanOne | PatternChar::ZeroOrOne => {
+                let Some(p) = patterns.pop() else {
+                    errors.push(error(ParseErrorKind::DanglingQuantifier));
+                    rest_expr = rest;
+                    continue;
+                };
+                let kind = match chr {
+                    PatternChar::MoreThanZero => PatternKind::MoreThanZero,
+                    PatternChar::MoreThanOne => PatternKind::MoreThanOne,
+                    _ => PatternKind::ZeroOrOne,
+                };
+                patterns.push(quantify(p, kind, pos(rest)));
             }
-            PatternChar::ZeroOrOne => {
-                // TODO:
-                // handle when pop method returns None
-                if let Some(p) = patterns.pop() {
-                    patterns.push(Pattern::ZeroOrOne(Box::new(p)));
+            PatternChar::PGroupOpen | PatternChar::NGroupOpen => {
+                let parsed = parse(rest, end, ctx.enter(Nesting::Bracket), errors, names);
+
+                if parsed.last_char.is_none() {
+                    errors.push(error(ParseErrorKind::UnclosedBracket));
                 }
-            }
-            PatternChar::PGroupOpen => {
-                let ParsedPatterns {
-                    inner, remaining, ..
-                } = parse_pattern(rest);
-                patterns.push(Pattern::PGroup(inner));
-                rest = remaining;
-            }
-            PatternChar::NGroupOpen => {
-                let ParsedPatterns {
-                    inner, remaining, ..
-                } = parse_pattern(rest);
-                patterns.push(Pattern::NGroup(inner));
-                rest = remaining;
-            }
-            PatternChar::GroupClose => {
-                return ParsedPatterns {
-                    inner: patterns,
-                    remaining: rest,
-                    last_char: Some(PatternChar::GroupClose),
+
+                let kind = match chr {
+                    PatternChar::PGroupOpen => PatternKind::PGroup(parsed.inner),
+                    _ => PatternKind::NGroup(parsed.inner),
                 };
+                patterns.push(Pattern {
+                    kind,
+                    span: Span {
+                        start,
+                        end: pos(parsed.remaining),
+                    },
+                });
+                rest = parsed.remaining;
             }
-            PatternChar::AltOpen => {
+            PatternChar::AltOpen(kind) => {
+                if let GroupKind::Named(name) = kind {
+                    if names.contains(&name) {
+                        // The name ends right before the `>` that ends `rest`.
+                        let name_end = pos(rest) - 1;
+                        errors.push(ParseError::new(
+                            ParseErrorKind::DuplicateName,
+                            name_end - name.len(),
+                            name_end,
+                        ));
+                    }
+
+                    names.push(name);
+                }
+
                 let mut inners: Vec<Vec<Pattern<'a>>> = vec![];
-                let mut parsed = parse_pattern(rest);
+                let mut parsed = parse(rest, end, ctx.enter(Nesting::Group), errors, names);
 
                 inners.push(parsed.inner);
-                rest = parsed.remaining;
 
-                while parsed
-                    .last_char
-                    .is_some_and(|c| matches!(c, PatternChar::AltDelimiter))
-                {
-                    parsed = parse_pattern(rest);
+                while matches!(parsed.last_char, Some(PatternChar::AltDelimiter)) {
+                    parsed = parse(
+                        parsed.remaining,
+                        end,
+                        ctx.enter(Nesting::Group),
+                        errors,
+                        names,
+                    );
 
                     inners.push(parsed.inner);
-                    rest = parsed.remaining;
                 }
 
-                patterns.push(Pattern::Alternation(inners));
-            }
-            PatternChar::AltClose => {
-                return ParsedPatterns {
-                    inner: patterns,
-                    remaining: rest,
-                    last_char: Some(PatternChar::AltClose),
-                };
+                if parsed.last_char.is_none() {
+                    errors.push(error(ParseErrorKind::UnclosedGroup));
+                }
+
+                rest = parsed.remaining;
+                patterns.push(Pattern {
+                    kind: match kind {
+                        GroupKind::Capturing => PatternKind::Alternation(inners),
+                        GroupKind::NonCapturing => PatternKind::NonCapturing(inners),
+                        GroupKind::Named(name) => PatternKind::Named(name, inners),
+                    },
+                    span: Span {
+                        start,
+                        end: pos(rest),
+                    },
+                });
             }
-            PatternChar::AltDelimiter => {
-                return ParsedPatterns {
-                    inner: patterns,
-                    remaining: rest,
-                    last_char: Some(PatternChar::AltDelimiter),
+            PatternChar::GroupClose | PatternChar::AltClose | PatternChar::AltDelimiter => {
+                let (closes, outer, kind) = match chr {
+                    PatternChar::GroupClose => (
+                        Nesting::Bracket,
+                        ctx.brackets,
+                        ParseErrorKind::UnopenedBracket,
+                    ),
+                    PatternChar::AltClose => {
+                        (Nesting::Group, ctx.groups, ParseErrorKind::UnopenedGroup)
+                    }
+                    // Brackets are more likely meant to hold a literal `|`
+                    // than to be missing their `]`.
+                    _ => (Nesting::Group, false, ParseErrorKind::BareAlternation),
                 };
+
+                if ctx.inside == closes {
+                    return ParsedPatterns {
+                        inner: patterns,
+                        remaining: rest,
+                        last_char: Some(chr),
+                    };
+                }
+
+                // Leave the character to the enclosing group or bracket.
+                if outer {
+                    return ParsedPatterns {
+                        inner: patterns,
+                        remaining: rest_expr,
+                        last_char: None,
+                    };
+                }
+
+                errors.push(error(kind));
             }
         }
 
@@ -305,32 +654,63 @@ pub fn parse_pattern<'a>(expr: &'a str) -> ParsedPatterns<'a> {
 
     ParsedPatterns {
         inner: patterns,
-        remaining: rest_expr,
+        remaining: "",
         last_char: None,
     }
 }
 
-fn is_ascii_alphanumeric(s: &str) -> bool {
-    is_ascii_alphabet(s) || is_ascii_digit(s) || s == "_"
+// Reads the `-z` that follows the start of a range `a-z` in brackets. A `-`
+// right before the closing `]` is a literal.
+fn range_end(rest: &str) -> Option<(PatternChar<'_>, &str)> {
+    let after = rest.strip_prefix('-')?;
+
+    if after.starts_with(']') {
+        return None;
+    }
+
+    PatternChar::pick(after)
 }
 
-fn is_ascii_alphabet(s: &str) -> bool {
-    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ".contains(s)
+// The char a range end stands for, if it is a single one.
+fn range_char(kind: &PatternKind) -> Option<char> {
+    match kind {
+        PatternKind::Lit(lit) => lit.chars().next(),
+        PatternKind::Byte(b) if b.is_ascii() => Some(*b as char),
+        _ => None,
+    }
 }
 
-fn is_ascii_digit(s: &str) -> bool {
-    "0123456789".contains(s)
+// Wraps `p` in a quantifier whose span runs from `p` to the quantifier's end.
+fn quantify<'a>(
+    p: Pattern<'a>,
+    kind: impl FnOnce(Box<Pattern<'a>>) -> PatternKind<'a>,
+    end: usize,
+) -> Pattern<'a> {
+    let start = p.span.start;
+
+    Pattern {
+        kind: kind(Box::new(p)),
+        span: Span { start, end },
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use PatternKind::*;
+
+    fn node(kind: PatternKind) -> Pattern {
+        Pattern {
+            kind,
+            span: Span::default(),
+        }
+    }
 
     #[test]
     fn it_parses_lit() {
         let expr = "a";
         let parsed = parse_pattern(expr);
-        assert_eq!(parsed.inner, vec![Pattern::Lit("a")]);
+        assert_eq!(parsed.inner, vec![node(Lit("a"))]);
         assert_eq!(parsed.remaining, "");
     }
 
@@ -338,7 +718,7 @@ mod tests {
     fn it_parses_alphanumeric() {
         let expr = "\\w";
         let parsed = parse_pattern(expr);
-        assert_eq!(parsed.inner, vec![Pattern::AlphaNumeric]);
+        assert_eq!(parsed.inner, vec![node(AlphaNumeric)]);
         assert_eq!(parsed.remaining, "");
     }
 
@@ -346,7 +726,15 @@ mod tests {
     fn it_parses_digit() {
         let expr = "\\d";
         let parsed = parse_pattern(expr);
-        assert_eq!(parsed.inner, vec![Pattern::Digit]);
+        assert_eq!(parsed.inner, vec![node(Digit)]);
+        assert_eq!(parsed.remaining, "");
+    }
+
+    #[test]
+    fn it_parses_whitespace() {
+        let expr = "\\s";
+        let parsed = parse_pattern(expr);
+        assert_eq!(parsed.inner, vec![node(Whitespace)]);
         assert_eq!(parsed.remaining, "");
     }
 
@@ -354,19 +742,49 @@ mod tests {
     fn it_parses_wildcard() {
         let expr = ".";
         let parsed = parse_pattern(expr);
-        assert_eq!(parsed.inner, vec![Pattern::Wildcard]);
+        assert_eq!(parsed.inner, vec![node(Wildcard)]);
         assert_eq!(parsed.remaining, "");
     }
 
+    #[test]
+    fn it_parses_hex_bytes() {
+        let expr = "\\xFF\\x41xg";
+        let parsed = parse_pattern(expr);
+        let expected = vec![
+            node(Byte(0xFF)),
+            node(Byte(0x41)),
+            node(Lit("x")),
+            node(Lit("g")),
+        ];
+        assert_eq!(parsed.inner, expected);
+        assert_eq!(parsed.remaining, "");
+    }
+
+    #[test]
+    fn it_parses_control_escapes() {
+        for (expr, lit) in [
+            ("\\t", "\t"),
+            ("\\n", "\n"),
+            ("\\r", "\r"),
+            ("\\f", "\x0C"),
+            ("\\v", "\x0B"),
+            ("\\0", "\0"),
+        ] {
+            let parsed = parse_pattern(expr);
+            assert_eq!(parsed.inner, vec![node(Lit(lit))], "{expr}");
+            assert_eq!(Concat(&parsed.patterns()).to_string(), expr);
+        }
+    }
+
     #[test]
     fn it_parses_positive_group() {
         let expr = "[abc]";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::PGroup(vec![
-            Pattern::Lit("a"),
-            Pattern::Lit("b"),
-            Pattern::Lit("c"),
-        ])];
+        let expected = vec![node(PGroup(vec![
+            node(Lit("a")),
+            node(Lit("b")),
+            node(Lit("c")),
+        ]))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
@@ -375,30 +793,94 @@ mod tests {
     fn it_parses_negative_group() {
         let expr = "[^xyz]";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::NGroup(vec![
-            Pattern::Lit("x"),
-            Pattern::Lit("y"),
-            Pattern::Lit("z"),
-        ])];
+        let expected = vec![node(NGroup(vec![
+            node(Lit("x")),
+            node(Lit("y")),
+            node(Lit("z")),
+        ]))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
 
+    #[test]
+    fn it_parses_ranges() {
+        let parsed = parse_pattern("[a-c-][^-0-9]");
+        let expected = vec![
+            node(PGroup(vec![node(Range('a', 'c')), node(Lit("-"))])),
+            node(NGroup(vec![node(Lit("-")), node(Range('0', '9'))])),
+        ];
+        assert_eq!(parsed.inner, expected);
+
+        let parsed = parse_pattern("a-c[\\--/]");
+        let expected = vec![
+            node(Lit("a")),
+            node(Lit("-")),
+            node(Lit("c")),
+            node(PGroup(vec![node(Range('-', '/'))])),
+        ];
+        assert_eq!(parsed.inner, expected);
+    }
+
+    #[test]
+    fn it_rejects_bad_escapes_and_ranges() {
+        let errors = |expr| {
+            parse_pattern_at(expr, 0)
+                .unwrap_err()
+                .into_iter()
+                .map(|err| (err.kind, err.span.start, err.span.end))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors("\\q\\xg\\"),
+            [
+                (ParseErrorKind::BadEscape, 0, 2),
+                (ParseErrorKind::BadEscape, 2, 4),
+                (ParseErrorKind::BadEscape, 5, 6),
+            ]
+        );
+        assert_eq!(
+            errors("[z-a][\\d-z]x[a-é]"),
+            [
+                (ParseErrorKind::BadRange, 1, 4),
+                (ParseErrorKind::BadRange, 6, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_duplicate_group_names() {
+        let errors = parse_pattern_at("(?P<a>x)(?<b>y)((?<a>z)|(?P<b>w))", 0).unwrap_err();
+        let spans: Vec<_> = errors
+            .iter()
+            .map(|err| (err.kind, err.span.start, err.span.end))
+            .collect();
+
+        assert_eq!(
+            spans,
+            [
+                (ParseErrorKind::DuplicateName, 19, 20),
+                (ParseErrorKind::DuplicateName, 28, 29),
+            ]
+        );
+        assert!(parse_pattern_at("(?P<a>x)(?P<ab>y)", 0).is_ok());
+    }
+
     #[test]
     fn it_parses_more_than_one_pattern() {
         let expr = "\\w+";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::MoreThanOne(Box::new(Pattern::AlphaNumeric))];
+        let expected = vec![node(MoreThanOne(Box::new(node(AlphaNumeric))))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
 
         let expr = "[abc]+";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::MoreThanOne(Box::new(Pattern::PGroup(vec![
-            Pattern::Lit("a"),
-            Pattern::Lit("b"),
-            Pattern::Lit("c"),
-        ])))];
+        let expected = vec![node(MoreThanOne(Box::new(node(PGroup(vec![
+            node(Lit("a")),
+            node(Lit("b")),
+            node(Lit("c")),
+        ])))))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
@@ -407,17 +889,17 @@ mod tests {
     fn it_parses_more_than_zero_pattern() {
         let expr = "\\w*";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::MoreThanZero(Box::new(Pattern::AlphaNumeric))];
+        let expected = vec![node(MoreThanZero(Box::new(node(AlphaNumeric))))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
 
         let expr = "[abc]*";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::MoreThanZero(Box::new(Pattern::PGroup(vec![
-            Pattern::Lit("a"),
-            Pattern::Lit("b"),
-            Pattern::Lit("c"),
-        ])))];
+        let expected = vec![node(MoreThanZero(Box::new(node(PGroup(vec![
+            node(Lit("a")),
+            node(Lit("b")),
+            node(Lit("c")),
+        ])))))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
@@ -426,17 +908,17 @@ mod tests {
     fn it_parses_zero_or_one_pattern() {
         let expr = "\\w?";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::ZeroOrOne(Box::new(Pattern::AlphaNumeric))];
+        let expected = vec![node(ZeroOrOne(Box::new(node(AlphaNumeric))))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
 
         let expr = "[abc]?";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::ZeroOrOne(Box::new(Pattern::PGroup(vec![
-            Pattern::Lit("a"),
-            Pattern::Lit("b"),
-            Pattern::Lit("c"),
-        ])))];
+        let expected = vec![node(ZeroOrOne(Box::new(node(PGroup(vec![
+            node(Lit("a")),
+            node(Lit("b")),
+            node(Lit("c")),
+        ])))))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
@@ -445,19 +927,19 @@ mod tests {
     fn it_parses_nested_group() {
         let expr = "[a[bc]]";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::PGroup(vec![
-            Pattern::Lit("a"),
-            Pattern::PGroup(vec![Pattern::Lit("b"), Pattern::Lit("c")]),
-        ])];
+        let expected = vec![node(PGroup(vec![
+            node(Lit("a")),
+            node(PGroup(vec![node(Lit("b")), node(Lit("c"))])),
+        ]))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
 
         let expr = "[a[^bc]]";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::PGroup(vec![
-            Pattern::Lit("a"),
-            Pattern::NGroup(vec![Pattern::Lit("b"), Pattern::Lit("c")]),
-        ])];
+        let expected = vec![node(PGroup(vec![
+            node(Lit("a")),
+            node(NGroup(vec![node(Lit("b")), node(Lit("c"))])),
+        ]))];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
@@ -467,13 +949,13 @@ mod tests {
         let expr = "\\d apple";
         let parsed = parse_pattern(expr);
         let expected = vec![
-            Pattern::Digit,
-            Pattern::Lit(" "),
-            Pattern::Lit("a"),
-            Pattern::Lit("p"),
-            Pattern::Lit("p"),
-            Pattern::Lit("l"),
-            Pattern::Lit("e"),
+            node(Digit),
+            node(Lit(" ")),
+            node(Lit("a")),
+            node(Lit("p")),
+            node(Lit("p")),
+            node(Lit("l")),
+            node(Lit("e")),
         ];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
@@ -483,11 +965,72 @@ mod tests {
     fn it_parses_alternations() {
         let expr = "(cat|dog)";
         let parsed = parse_pattern(expr);
-        let expected = vec![Pattern::Alternation(vec![
-            vec![Pattern::Lit("c"), Pattern::Lit("a"), Pattern::Lit("t")],
-            vec![Pattern::Lit("d"), Pattern::Lit("o"), Pattern::Lit("g")],
-        ])];
+        let expected = vec![node(Alternation(vec![
+            vec![node(Lit("c")), node(Lit("a")), node(Lit("t"))],
+            vec![node(Lit("d")), node(Lit("o")), node(Lit("g"))],
+        ]))];
+        assert_eq!(parsed.inner, expected);
+        assert_eq!(parsed.remaining, "");
+    }
+
+    #[test]
+    fn it_parses_group_kinds() {
+        let parsed = parse_pattern("(?:a)(?P<x>b)(?<y_1>c)");
+        let expected = vec![
+            node(NonCapturing(vec![vec![node(Lit("a"))]])),
+            node(Named("x", vec![vec![node(Lit("b"))]])),
+            node(Named("y_1", vec![vec![node(Lit("c"))]])),
+        ];
         assert_eq!(parsed.inner, expected);
         assert_eq!(parsed.remaining, "");
     }
+
+    #[test]
+    fn it_records_the_span_of_every_node() {
+        let span = |p: &Pattern| (p.span.start, p.span.end);
+        let patterns = parse_pattern("a(bc|\\d)+[^x]").patterns();
+
+        assert_eq!(
+            patterns.iter().map(span).collect::<Vec<_>>(),
+            [(0, 1), (1, 9), (9, 13)]
+        );
+
+        let MoreThanOne(group) = &patterns[1].kind else {
+            panic!("expected a quantifier");
+        };
+        assert_eq!(span(group), (1, 8));
+
+        let branches = group.branches().expect("a group");
+        assert_eq!(span(&branches[0][1]), (3, 4));
+        assert_eq!(span(&branches[1][0]), (5, 7));
+
+        let patterns = parse_pattern_at("ab", 1).unwrap();
+        assert_eq!(span(&patterns[1]), (2, 3));
+    }
+
+    #[test]
+    fn it_displays_patterns_that_reparse_equal() {
+        let exprs = [
+            "\\d+ (apple|orange)s?",
+            "[^a\\]\\w]*x.\\s",
+            "(?:a|)(?<x>b+)(?P<y_1>c)",
+            "\\xFF\\x0a\\(\\$\\^\\.\\|\\?",
+            "((a|b)*c|\\\\)",
+            "n\\-[\\^b]",
+            "[a-z0-9_\\-]+[\\x00-\\x1f]",
+            "a\\tb[\\r\\n]\\0",
+        ];
+
+        for expr in exprs {
+            let patterns = parse_pattern(expr).patterns();
+            let text = Concat(&patterns).to_string();
+            let reparsed =
+                parse_pattern_at(&text, 0).unwrap_or_else(|err| panic!("{text}: {err:?}"));
+
+            assert_eq!(reparsed, patterns, "{expr} -> {text}");
+        }
+
+        let patterns = parse_pattern("(?<x>\\x0a)\\/[\\x41-Z-]").patterns();
+        assert_eq!(Concat(&patterns).to_string(), "(?P<x>\\x0A)/[A-Z\\-]");
+    }
 }
@@ -0,0 +1,307 @@
+use super::budget::Budget;
+use super::nfa::{Nfa, SparseSet, State, StateId};
+use crate::Result;
+
+/// Which match a search reports when several start at the same position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Prefers earlier alternation branches and greedy repetitions, like Perl.
+    #[default]
+    LeftmostFirst,
+    /// Prefers the longest match, like POSIX tools such as `grep -E`.
+    LeftmostLongest,
+}
+
+/// The active threads of a search, one per NFA state, with where each one
+/// started and the capture slots it has recorded so far.
+struct Threads {
+    set: SparseSet,
+    starts: Vec<usize>,
+    slots: Vec<Option<usize>>,
+    slots_per_thread: usize,
+}
+
+impl Threads {
+    fn new(nfa: &Nfa, slots_per_thread: usize) -> Self {
+        Self {
+            set: SparseSet::new(nfa.len()),
+            starts: vec![0; nfa.len()],
+            slots: vec![None; nfa.len() * slots_per_thread],
+            slots_per_thread,
+        }
+    }
+
+    fn slots(&self, id: StateId) -> &[Option<usize>] {
+        &self.slots[id * self.slots_per_thread..(id + 1) * self.slots_per_thread]
+    }
+
+    fn slots_mut(&mut self, id: StateId) -> &mut [Option<usize>] {
+        &mut self.slots[id * self.slots_per_thread..(id + 1) * self.slots_per_thread]
+    }
+}
+
+enum Frame {
+    Explore(StateId),
+    Restore(usize, Option<usize>),
+}
+
+/// Finds the span of the leftmost match starting at or after `at`, and fills
+/// `slots` with the positions its capture groups recorded. Groups without a
+/// slot in `slots` are not tracked.
+///
+/// Simulates the NFA like [`Nfa::is_match`], but every active state also
+/// remembers where its thread started. Threads are kept in priority order, and
+/// since threads are only ever started after all others, that order also
+/// keeps the earliest start whenever two threads reach the same state.
+pub fn find_at(
+    nfa: &Nfa,
+    hay: &[u8],
+    at: usize,
+    kind: MatchKind,
+    slots: &mut [Option<usize>],
+    budget: &mut Budget,
+) -> Result<Option<(usize, usize)>> {
+    let mut cur = Threads::new(nfa, slots.len());
+    let mut next = Threads::new(nfa, slots.len());
+    let mut scratch = vec![None; slots.len()];
+    let mut stack = vec![];
+    let mut best: Option<(usize, usize)> = None;
+    let mut pos = at;
+
+    loop {
+        if best.is_none() && (!nfa.start_anchor() || pos == 0) {
+            scratch.fill(None);
+            let start = nfa.start();
+            add_thread(nfa, &mut cur, start, pos, pos, &mut scratch, &mut stack);
+        }
+
+        if cur.set.is_empty() {
+            return Ok(best);
+        }
+
+        let unit = nfa.next_unit(hay, pos);
+        next.set.clear();
+        budget.spend(cur.set.len() as u64)?;
+
+        for id in cur.set.iter() {
+            let start = cur.starts[id];
+
+            if best.is_some_and(|(best_start, _)| start > best_start) {
+                continue;
+            }
+
+            match nfa.state(id) {
+                State::Match(_) => {
+                    if nfa.end_anchor() && pos != hay.len() {
+                        continue;
+                    }
+
+                    let replace = match (kind, best) {
+                        (MatchKind::LeftmostLongest, Some((s, e))) => start < s || pos > e,
+                        _ => true,
+                    };
+
+                    if replace {
+                        best = Some((start, pos));
+                        slots.copy_from_slice(cur.slots(id));
+                    }
+
+                    // Every thread after this one has a lower priority.
+                    if kind == MatchKind::LeftmostFirst {
+                        break;
+                    }
+                }
+                State::Char { set, next: to } => {
+                    if let Some((_, len)) = unit.filter(|&(unit, _)| set.contains(unit)) {
+                        scratch.copy_from_slice(cur.slots(id));
+                        add_thread(
+                            nfa,
+                            &mut next,
+                            *to,
+                            start,
+                            pos + len,
+                            &mut scratch,
+                            &mut stack,
+                        );
+                    }
+                }
+                State::Split(..) | State::Save { .. } => {}
+            }
+        }
+
+        let Some((_, len)) = unit else {
+            return Ok(best);
+        };
+
+        std::mem::swap(&mut cur, &mut next);
+        pos += len;
+    }
+}
+
+// Adds the thread at `id` and every thread reachable from it without consuming
+// a char, in priority order. `slots` holds the slots of the thread and is
+// restored before returning.
+fn add_thread(
+    nfa: &Nfa,
+    threads: &mut Threads,
+    id: StateId,
+    start: usize,
+    pos: usize,
+    slots: &mut [Option<usize>],
+    stack: &mut Vec<Frame>,
+) {
+    stack.push(Frame::Explore(id));
+
+    while let Some(frame) = stack.pop() {
+        let id = match frame {
+            Frame::Explore(id) => id,
+            Frame::Restore(slot, old) => {
+                slots[slot] = old;
+                continue;
+            }
+        };
+
+        if !threads.set.insert(id) {
+            continue;
+        }
+
+        match *nfa.state(id) {
+            State::Split(a, b) => {
+                // Push the preferred branch last so that it is visited first.
+                stack.push(Frame::Explore(b));
+                stack.push(Frame::Explore(a));
+            }
+            State::Save { slot, next } => {
+                if slot < slots.len() {
+                    stack.push(Frame::Restore(slot, slots[slot]));
+                    slots[slot] = Some(pos);
+                }
+                stack.push(Frame::Explore(next));
+            }
+            State::Char { .. } | State::Match(_) => {
+                threads.starts[id] = start;
+                threads.slots_mut(id).copy_from_slice(slots);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hir::translate;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    fn find(expr: &str, hay: &str, kind: MatchKind) -> Option<(usize, usize)> {
+        let nfa = Nfa::new(&translate(&parse_pattern(expr).patterns()), false, false);
+        find_at(
+            &nfa,
+            hay.as_bytes(),
+            0,
+            kind,
+            &mut [],
+            &mut Budget::unlimited(),
+        )
+        .unwrap()
+    }
+
+    fn captures(expr: &str, hay: &str, kind: MatchKind) -> Vec<Option<usize>> {
+        let nfa = Nfa::new(&translate(&parse_pattern(expr).patterns()), false, false);
+        let mut slots = vec![None; 4];
+        find_at(
+            &nfa,
+            hay.as_bytes(),
+            0,
+            kind,
+            &mut slots,
+            &mut Budget::unlimited(),
+        )
+        .unwrap();
+        slots
+    }
+
+    #[test]
+    fn it_finds_leftmost_first_matches() {
+        let kind = MatchKind::LeftmostFirst;
+        assert_eq!(find("(a|ab)(c|bcd)", "xabcd", kind), Some((1, 5)));
+        assert_eq!(find("(a|ab)c?", "xabc", kind), Some((1, 2)));
+        assert_eq!(find("a+", "baaab", kind), Some((1, 4)));
+        assert_eq!(find("(a|ab)", "ab", kind), Some((0, 1)));
+        assert_eq!(find("\\d+", "abc", kind), None);
+    }
+
+    #[test]
+    fn it_finds_leftmost_longest_matches() {
+        let kind = MatchKind::LeftmostLongest;
+        assert_eq!(find("(a|ab)c?", "xabc", kind), Some((1, 4)));
+        assert_eq!(find("(a|ab)", "ab", kind), Some((0, 2)));
+        assert_eq!(find("(a|ab)c", "abc", kind), Some((0, 3)));
+        assert_eq!(find("(b|ab+)", "abbb", kind), Some((0, 4)));
+    }
+
+    #[test]
+    fn it_finds_empty_matches() {
+        assert_eq!(find("a*", "bbb", MatchKind::LeftmostFirst), Some((0, 0)));
+        assert_eq!(find("a*", "baa", MatchKind::LeftmostLongest), Some((0, 0)));
+    }
+
+    #[test]
+    fn it_records_capture_groups() {
+        let kind = MatchKind::LeftmostFirst;
+        assert_eq!(
+            captures("(\\d+)-(\\d+)", "tel 12-345", kind),
+            [Some(4), Some(6), Some(7), Some(10)]
+        );
+        assert_eq!(
+            captures("(a|ab)(c|bcd)", "abcd", kind),
+            [Some(0), Some(1), Some(1), Some(4)]
+        );
+        assert_eq!(
+            captures("(a|ab)(c|bcd)", "abcd", MatchKind::LeftmostLongest),
+            [Some(0), Some(1), Some(1), Some(4)]
+        );
+        assert_eq!(
+            captures("(a)?(b)", "b", kind),
+            [None, None, Some(0), Some(1)]
+        );
+        assert_eq!(
+            captures("(?:(a)|b)+", "ab", kind),
+            [Some(0), Some(1), None, None]
+        );
+    }
+
+    #[test]
+    fn it_respects_anchors() {
+        let patterns = translate(&parse_pattern("a+").patterns());
+        let mut budget = Budget::unlimited();
+        let first = MatchKind::LeftmostFirst;
+
+        let nfa = Nfa::new(&patterns, true, false);
+        assert_eq!(
+            find_at(&nfa, b"aab", 0, first, &mut [], &mut budget).unwrap(),
+            Some((0, 2))
+        );
+        assert_eq!(
+            find_at(&nfa, b"baa", 0, first, &mut [], &mut budget).unwrap(),
+            None
+        );
+
+        let nfa = Nfa::new(&patterns, false, true);
+        assert_eq!(
+            find_at(&nfa, b"aaba", 0, first, &mut [], &mut budget).unwrap(),
+            Some((3, 4))
+        );
+        assert_eq!(
+            find_at(
+                &nfa,
+                b"aaba",
+                4,
+                MatchKind::LeftmostLongest,
+                &mut [],
+                &mut budget
+            )
+            .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, PoisonError};
+
+/// Values such as DFA caches, lent out to one search at a time.
+///
+/// A search that finds the pool empty creates a new value instead of waiting,
+/// so searches on several threads never block each other for longer than it
+/// takes to push or pop a value.
+#[derive(Debug)]
+pub struct Pool<T> {
+    free: Mutex<Vec<T>>,
+}
+
+impl<T> Pool<T> {
+    pub fn new() -> Self {
+        Self {
+            free: Mutex::new(vec![]),
+        }
+    }
+
+    /// Takes a value out of the pool, or creates one with `create`. It goes
+    /// back into the pool when the guard is dropped.
+    pub fn get(&self, create: impl FnOnce() -> T) -> PoolGuard<'_, T> {
+        let value = self
+            .free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .unwrap_or_else(create);
+
+        PoolGuard {
+            pool: self,
+            value: Some(value),
+        }
+    }
+}
+
+/// Clones start out empty, since the values are only caches.
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct PoolGuard<'p, T> {
+    pool: &'p Pool<T>,
+    // Only `None` while being dropped.
+    value: Option<T>,
+}
+
+impl<T> Deref for PoolGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("pool value taken before drop")
+    }
+}
+
+impl<T> DerefMut for PoolGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("pool value taken before drop")
+    }
+}
+
+impl<T> Drop for PoolGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.pool
+                .free
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reuses_returned_values() {
+        let pool = Pool::new();
+
+        {
+            let mut first = pool.get(Vec::new);
+            first.push(1);
+
+            let second = pool.get(|| vec![2]);
+            assert_eq!(*second, [2]);
+        }
+
+        let mut reused = [pool.get(Vec::new), pool.get(Vec::new)];
+        reused.sort_by_key(|v| v[0]);
+        assert_eq!(*reused[0], [1]);
+        assert_eq!(*reused[1], [2]);
+        assert!(pool.clone().get(Vec::new).is_empty());
+    }
+}
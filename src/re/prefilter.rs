@@ -0,0 +1,394 @@
+use super::aho::AhoCorasick;
+use super::charset::CharSet;
+use super::hir::Hir;
+use super::memmem::Finder;
+use super::simd::ByteClass;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LiteralKind {
+    Prefix,
+    Suffix,
+    Inner,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literals {
+    Single(Finder),
+    // One literal per branch of a leading alternation.
+    Multi(Box<AhoCorasick>),
+    // The ASCII bytes a match starts with, when it has no literal at all.
+    Class(ByteClass),
+}
+
+/// A literal that every match must contain, used to rule out haystacks and to
+/// skip ahead to candidate positions before running the full matcher. Without
+/// any literal, the bytes every match starts with are used instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefilter {
+    literals: Literals,
+    kind: LiteralKind,
+    start_anchor: bool,
+    end_anchor: bool,
+}
+
+impl Prefilter {
+    pub fn new(patterns: &[Hir], start_anchor: bool, end_anchor: bool) -> Option<Self> {
+        if let Some(prefixes) = branch_prefixes(patterns) {
+            return Some(Self {
+                literals: Literals::Multi(Box::new(AhoCorasick::new(prefixes))),
+                kind: LiteralKind::Prefix,
+                start_anchor,
+                end_anchor,
+            });
+        }
+
+        let mut runs = literal_runs(patterns);
+
+        let run = match runs.iter().position(|run| run.prefix) {
+            Some(i) => runs.swap_remove(i),
+            None => {
+                let Some((i, _)) = runs
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, run)| run.literal.len())
+                else {
+                    return Some(Self {
+                        literals: Literals::Class(first_bytes(patterns)?),
+                        kind: LiteralKind::Prefix,
+                        start_anchor,
+                        end_anchor,
+                    });
+                };
+                runs.swap_remove(i)
+            }
+        };
+
+        let kind = if run.prefix {
+            LiteralKind::Prefix
+        } else if run.suffix {
+            LiteralKind::Suffix
+        } else {
+            LiteralKind::Inner
+        };
+
+        Some(Self {
+            literals: Literals::Single(Finder::new(run.literal.as_bytes())),
+            kind,
+            start_anchor,
+            end_anchor,
+        })
+    }
+
+    pub fn kind(&self) -> LiteralKind {
+        self.kind
+    }
+
+    /// The literal every match ends with, if there is a single one.
+    pub fn suffix(&self) -> Option<&[u8]> {
+        match (&self.literals, self.kind) {
+            (Literals::Single(finder), LiteralKind::Suffix) => Some(finder.needle()),
+            _ => None,
+        }
+    }
+
+    /// The literal in the middle of every match, if it is not a prefix or a
+    /// suffix.
+    pub fn inner(&self) -> Option<&[u8]> {
+        match (&self.literals, self.kind) {
+            (Literals::Single(finder), LiteralKind::Inner) => Some(finder.needle()),
+            _ => None,
+        }
+    }
+
+    /// Returns `false` only if `hay` cannot possibly match.
+    pub fn may_match(&self, hay: &[u8]) -> bool {
+        match (&self.literals, self.kind) {
+            (Literals::Single(finder), LiteralKind::Prefix) if self.start_anchor => {
+                hay.starts_with(finder.needle())
+            }
+            (Literals::Single(finder), LiteralKind::Suffix) if self.end_anchor => {
+                hay.ends_with(finder.needle())
+            }
+            (Literals::Multi(ac), LiteralKind::Prefix) if self.start_anchor => ac.is_prefix_of(hay),
+            (Literals::Class(class), _) if self.start_anchor => {
+                hay.first().is_some_and(|&b| class.contains(b))
+            }
+            _ => self.find(hay, 0).is_some(),
+        }
+    }
+
+    /// Returns a position at or after `from` where the next occurrence of the
+    /// literal may start. No occurrence starts in between.
+    pub fn find(&self, hay: &[u8], from: usize) -> Option<usize> {
+        match &self.literals {
+            Literals::Single(finder) => finder.find_at(hay, from),
+            Literals::Multi(ac) => ac.find_candidate(hay, from),
+            Literals::Class(class) => class.find(hay, from),
+        }
+    }
+}
+
+// The bytes every match starts with, if they are all ASCII and a few ranges
+// cover them. A non-ASCII byte may be in the middle of a char.
+fn first_bytes(patterns: &[Hir]) -> Option<ByteClass> {
+    let (set, nullable) = first_chars(patterns);
+
+    if nullable || set.ranges().iter().any(|&(_, hi)| hi > 0x7F) {
+        return None;
+    }
+
+    ByteClass::new(set.ranges().iter().map(|&(lo, hi)| (lo as u8, hi as u8)))
+}
+
+// The chars a match of `patterns` can start with, and whether it can be empty.
+fn first_chars(patterns: &[Hir]) -> (CharSet, bool) {
+    let mut acc = CharSet::empty();
+
+    for pat in patterns {
+        let (set, nullable) = first_char(pat);
+        acc = acc.union(&set);
+
+        if !nullable {
+            return (acc, false);
+        }
+    }
+
+    (acc, true)
+}
+
+fn first_char(pat: &Hir) -> (CharSet, bool) {
+    match pat {
+        Hir::Literal(lit) => match lit.chars().next() {
+            Some(c) => (CharSet::single(c), false),
+            None => (CharSet::empty(), true),
+        },
+        Hir::Class(set) => (set.clone(), false),
+        Hir::Plus(p) => first_char(p),
+        Hir::Star(p) | Hir::Optional(p) => (first_char(p).0, true),
+        Hir::Capture { branches, .. } | Hir::Group(branches) => {
+            branches.iter().map(|branch| first_chars(branch)).fold(
+                (CharSet::empty(), false),
+                |(acc, nullable), (set, empty)| (acc.union(&set), nullable || empty),
+            )
+        }
+    }
+}
+
+// The literal prefix of every branch of a leading alternation, if none of
+// them is empty. A leading literal followed by a group is how an alternation
+// looks once its common prefix is factored out, so it counts as one too.
+fn branch_prefixes(patterns: &[Hir]) -> Option<Vec<String>> {
+    let prefixes = match patterns {
+        [Hir::Literal(_), Hir::Group(_), ..] => prefixes(&[patterns[..2].to_vec()], false)?,
+        [first, ..] => prefixes(first.branches()?, false)?,
+        [] => return None,
+    };
+
+    (prefixes.len() > 1).then_some(prefixes)
+}
+
+// Expands branches that had their common prefix factored out, so that each
+// of the original branches gets its own prefix. Inside such a group, a branch
+// may have an empty prefix, since the factored one comes before it.
+fn prefixes(branches: &[Vec<Hir>], factored: bool) -> Option<Vec<String>> {
+    let mut out = vec![];
+
+    for branch in branches {
+        match branch.as_slice() {
+            [Hir::Literal(prefix), Hir::Group(rest)] => {
+                for p in prefixes(rest, true)? {
+                    out.push(format!("{prefix}{p}"));
+                }
+            }
+            branch => match literal_runs(branch).into_iter().find(|run| run.prefix) {
+                Some(run) => out.push(run.literal),
+                None if factored => out.push(String::new()),
+                None => return None,
+            },
+        }
+    }
+
+    Some(out)
+}
+
+#[derive(Debug)]
+struct Run {
+    literal: String,
+    // Whether the run starts at the very beginning or ends at the very end of
+    // the patterns it was collected from.
+    prefix: bool,
+    suffix: bool,
+}
+
+// Collects the maximal sequences of literal chars that appear one after the
+// other in every match.
+fn literal_runs(patterns: &[Hir]) -> Vec<Run> {
+    let mut runs = vec![];
+    let mut cur: Option<Run> = None;
+
+    for (i, pat) in patterns.iter().enumerate() {
+        match pat {
+            Hir::Literal(lit) => extend(&mut cur, lit, i == 0),
+            // A group with a single branch is matched exactly like its contents.
+            Hir::Capture { branches, .. } | Hir::Group(branches) if branches.len() == 1 => {
+                let branch = &branches[0];
+                let inner = literal_runs(branch);
+
+                if inner.is_empty() && !branch.is_empty() {
+                    runs.extend(cur.take());
+                }
+
+                for run in inner {
+                    if !run.prefix {
+                        runs.extend(cur.take());
+                    }
+
+                    extend(&mut cur, &run.literal, i == 0 && run.prefix);
+
+                    if !run.suffix {
+                        runs.extend(cur.take());
+                    }
+                }
+            }
+            Hir::Plus(p) => {
+                if let Hir::Literal(lit) = p.as_ref() {
+                    extend(&mut cur, lit, i == 0);
+                }
+                runs.extend(cur.take());
+            }
+            _ => runs.extend(cur.take()),
+        }
+    }
+
+    if let Some(mut run) = cur {
+        run.suffix = true;
+        runs.push(run);
+    }
+
+    runs
+}
+
+fn extend(cur: &mut Option<Run>, literal: &str, prefix: bool) {
+    cur.get_or_insert_with(|| Run {
+        literal: String::new(),
+        prefix,
+        suffix: false,
+    })
+    .literal
+    .push_str(literal);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hir::translate;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    fn prefilter(expr: &str) -> Option<Prefilter> {
+        Prefilter::new(&translate(&parse_pattern(expr).patterns()), false, false)
+    }
+
+    fn single(literal: &str) -> Literals {
+        Literals::Single(Finder::new(literal.as_bytes()))
+    }
+
+    #[test]
+    fn it_extracts_prefixes() {
+        let pre = prefilter("GET \\w+").unwrap();
+        assert_eq!(pre.literals, single("GET "));
+        assert_eq!(pre.kind(), LiteralKind::Prefix);
+    }
+
+    #[test]
+    fn it_extracts_suffixes() {
+        let pre = prefilter("\\d+\\.log").unwrap();
+        assert_eq!(pre.literals, single(".log"));
+        assert_eq!(pre.kind(), LiteralKind::Suffix);
+        assert_eq!(pre.suffix(), Some(&b".log"[..]));
+    }
+
+    #[test]
+    fn it_extracts_inner_literals() {
+        let pre = prefilter("\\d+ ERROR \\w+").unwrap();
+        assert_eq!(pre.literals, single(" ERROR "));
+        assert_eq!(pre.kind(), LiteralKind::Inner);
+        assert_eq!(pre.inner(), Some(&b" ERROR "[..]));
+
+        let pre = prefilter("\\d(abc)\\d").unwrap();
+        assert_eq!(pre.literals, single("abc"));
+    }
+
+    #[test]
+    fn it_extracts_alternation_prefixes() {
+        let pre = prefilter("(GET|POST|PUT) /\\w+").unwrap();
+        assert_eq!(
+            pre.literals,
+            Literals::Multi(Box::new(AhoCorasick::new(["GET", "POST", "PUT"])))
+        );
+        assert_eq!(pre.kind(), LiteralKind::Prefix);
+        assert!(pre.may_match(b"x PUT /a"));
+        assert!(!pre.may_match(b"x PATCH /a"));
+
+        let pre = prefilter("(GET|\\w+) /").unwrap();
+        assert_eq!(pre.literals, single(" /"));
+    }
+
+    #[test]
+    fn it_expands_factored_alternation_prefixes() {
+        let multi = |prefixes: &[&str]| Literals::Multi(Box::new(AhoCorasick::new(prefixes)));
+
+        let pre = prefilter("(PUT|POST|PATCH) /").unwrap();
+        assert_eq!(pre.literals, multi(&["PUT", "POST", "PATCH"]));
+        assert!(!pre.may_match(b"x PING /a"));
+
+        let pre = prefilter("(?:error|errno)\\d").unwrap();
+        assert_eq!(pre.literals, multi(&["error", "errno"]));
+
+        let pre = prefilter("(ab|a\\d)").unwrap();
+        assert_eq!(pre.literals, multi(&["ab", "a"]));
+    }
+
+    #[test]
+    fn it_ignores_optional_parts() {
+        assert!(prefilter("\\d?\\w*").is_none());
+        assert!(prefilter("(cat|)").is_none());
+        assert_eq!(prefilter("a?bc").unwrap().literals, single("bc"));
+    }
+
+    #[test]
+    fn it_rules_out_haystacks() {
+        let pre = prefilter("\\d+ ERROR \\w+").unwrap();
+        assert!(pre.may_match(b"12 ERROR disk"));
+        assert!(!pre.may_match(b"12 WARN disk"));
+
+        let pre =
+            Prefilter::new(&translate(&parse_pattern("ab\\d").patterns()), true, false).unwrap();
+        assert!(pre.may_match(b"ab1"));
+        assert!(!pre.may_match(b"xab1"));
+    }
+
+    #[test]
+    fn it_falls_back_to_the_first_bytes() {
+        let pre = prefilter("\\d+").unwrap();
+        assert_eq!(pre.kind(), LiteralKind::Prefix);
+        assert_eq!(pre.find(b"ab 12", 0), Some(3));
+        assert!(!pre.may_match(b"no digits"));
+
+        let pre = prefilter("(\\s|-)?[abc]\\d").unwrap();
+        assert_eq!(pre.find(b"XY-b1", 0), Some(2));
+        assert_eq!(pre.find(b"XY-b1", 3), Some(3));
+
+        assert!(prefilter("\\d*").is_none());
+        assert!(prefilter("\\w+").is_some());
+        assert!(prefilter(".\\d").is_none());
+        assert!(prefilter("[^a]\\d").is_none());
+    }
+
+    #[test]
+    fn it_finds_occurrences() {
+        let pre = prefilter("ab\\d").unwrap();
+        assert_eq!(pre.find(b"aab ab", 0), Some(1));
+        assert_eq!(pre.find(b"aab ab", 2), Some(4));
+        assert_eq!(pre.find(b"aab ab", 5), None);
+    }
+}
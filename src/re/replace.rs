@@ -0,0 +1,107 @@
+use super::{Captures, Regex};
+use std::borrow::Cow;
+
+/// Produces the replacement for each match in [`Regex::replace`] and friends.
+///
+/// A `&str` or `String` is a template expanded with [`Captures::expand`], and
+/// a closure returns the replacement for the captures it is given.
+pub trait Replacer {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String);
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+impl Regex {
+    /// Replaces the leftmost match in `s`.
+    pub fn replace<'h, R: Replacer>(&self, s: &'h str, rep: R) -> Cow<'h, str> {
+        self.replacen(s, 1, rep)
+    }
+
+    /// Replaces every non-overlapping match in `s`.
+    pub fn replace_all<'h, R: Replacer>(&self, s: &'h str, rep: R) -> Cow<'h, str> {
+        self.replacen(s, 0, rep)
+    }
+
+    /// Replaces the first `limit` non-overlapping matches in `s`, or all of
+    /// them if `limit` is 0. Returns `s` itself if nothing matched.
+    pub fn replacen<'h, R: Replacer>(&self, s: &'h str, limit: usize, mut rep: R) -> Cow<'h, str> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut matches = self.captures_iter(s).take(limit).peekable();
+
+        if matches.peek().is_none() {
+            return Cow::Borrowed(s);
+        }
+
+        let mut out = String::with_capacity(s.len());
+        let mut last = 0;
+
+        for caps in matches {
+            let m = caps.get(0).expect("group 0 always matches");
+            out.push_str(&s[last..m.start]);
+            rep.replace_append(&caps, &mut out);
+            last = m.end;
+        }
+
+        out.push_str(&s[last..]);
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_replaces_with_templates() {
+        let r = Regex::new("(\\w+)@(?P<host>\\w+)").unwrap();
+        assert_eq!(
+            r.replace("mail bob@home or amy@work", "$host:$1"),
+            "mail home:bob or amy@work"
+        );
+        assert_eq!(
+            r.replace_all("mail bob@home or amy@work", "${host}_$$"),
+            "mail home_$ or work_$"
+        );
+        assert_eq!(
+            r.replacen("a@b c@d e@f", 2, String::from("[$0]")),
+            "[a@b] [c@d] e@f"
+        );
+    }
+
+    #[test]
+    fn it_replaces_with_closures() {
+        let r = Regex::new("\\d+").unwrap();
+        let doubled = r.replace_all("3 apples and 12 pears", |caps: &Captures| {
+            let n: u32 = caps.get(0).unwrap().text.parse().unwrap();
+            (n * 2).to_string()
+        });
+        assert_eq!(doubled, "6 apples and 24 pears");
+    }
+
+    #[test]
+    fn it_borrows_when_nothing_matches() {
+        let r = Regex::new("\\d").unwrap();
+        assert!(matches!(r.replace_all("none", "x"), Cow::Borrowed("none")));
+        assert_eq!(Regex::new("a*").unwrap().replace_all("bab", "-"), "-b-b-");
+    }
+}
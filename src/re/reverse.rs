@@ -0,0 +1,217 @@
+use super::budget::Budget;
+use super::dfa::{self, Cache};
+use super::hir::Hir;
+use super::memmem::Finder;
+use super::nfa::Nfa;
+use super::pool::Pool;
+use crate::Result;
+
+/// Matches patterns backwards from a fixed end position.
+///
+/// For a pattern ending in `$`, this only reads the tail of the haystack
+/// instead of trying every start position from the front.
+#[derive(Debug, Clone)]
+pub struct Reverse {
+    nfa: Nfa,
+    cache: Pool<Cache>,
+}
+
+impl Reverse {
+    pub fn new(patterns: &[Hir], start_anchor: bool) -> Self {
+        Self {
+            nfa: Nfa::reverse(patterns, start_anchor, true),
+            cache: Pool::new(),
+        }
+    }
+
+    /// Whether a match ends exactly at `end`.
+    pub fn is_match_at(&self, hay: &[u8], end: usize, budget: &mut Budget) -> Result<bool> {
+        let hay = &hay[..end];
+        let mut cache = self.cache.get(|| Cache::new(&self.nfa));
+
+        match dfa::is_match(&self.nfa, &mut cache, hay, None, budget)? {
+            Some(matched) => Ok(matched),
+            None => self.nfa.is_match(hay, budget),
+        }
+    }
+}
+
+/// Finds the literal every match ends with and matches backwards from each
+/// occurrence of it.
+#[derive(Debug, Clone)]
+pub struct ReverseSuffix {
+    suffix: Finder,
+    reverse: Reverse,
+}
+
+impl ReverseSuffix {
+    pub fn new(patterns: &[Hir], suffix: &[u8]) -> Self {
+        Self {
+            suffix: Finder::new(suffix),
+            reverse: Reverse::new(patterns, false),
+        }
+    }
+
+    /// Returns `None` once the backward searches have done more work than
+    /// reading `hay` twice, since occurrences close to each other may make them
+    /// read the same bytes over and over. The caller should then search
+    /// forwards instead.
+    pub fn is_match(&self, hay: &[u8], budget: &mut Budget) -> Result<Option<bool>> {
+        let spent = budget.steps();
+        let mut from = 0;
+
+        while let Some(pos) = self.suffix.find_at(hay, from) {
+            let end = pos + self.suffix.needle().len();
+
+            if self.reverse.is_match_at(hay, end, budget)? {
+                return Ok(Some(true));
+            }
+
+            if budget.steps() - spent > 2 * hay.len() as u64 {
+                return Ok(None);
+            }
+
+            from = pos + 1;
+        }
+
+        Ok(Some(false))
+    }
+}
+
+/// Finds a literal in the middle of every match, matches backwards from each
+/// occurrence of it to find a start, and then forwards from its end.
+#[derive(Debug, Clone)]
+pub struct ReverseInner {
+    inner: Finder,
+    reverse: Reverse,
+    forward: Nfa,
+    cache: Pool<Cache>,
+}
+
+impl ReverseInner {
+    /// Splits `patterns` around the top-level literal equal to `inner`, if
+    /// there is one.
+    pub fn new(patterns: &[Hir], inner: &[u8]) -> Option<Self> {
+        let i = patterns
+            .iter()
+            .position(|pat| matches!(pat, Hir::Literal(lit) if lit.as_bytes() == inner))?;
+
+        Some(Self {
+            inner: Finder::new(inner),
+            reverse: Reverse::new(&patterns[..i], false),
+            forward: Nfa::new(&patterns[i + 1..], true, false),
+            cache: Pool::new(),
+        })
+    }
+
+    /// Returns `None` once the searches have done more work than reading `hay`
+    /// twice, like [`ReverseSuffix::is_match`].
+    pub fn is_match(&self, hay: &[u8], budget: &mut Budget) -> Result<Option<bool>> {
+        let spent = budget.steps();
+        let mut from = 0;
+
+        while let Some(pos) = self.inner.find_at(hay, from) {
+            if self.reverse.is_match_at(hay, pos, budget)?
+                && self.is_match_from(hay, pos, budget)?
+            {
+                return Ok(Some(true));
+            }
+
+            if budget.steps() - spent > 2 * hay.len() as u64 {
+                return Ok(None);
+            }
+
+            from = pos + 1;
+        }
+
+        Ok(Some(false))
+    }
+
+    // Whether the rest of a match starts right after the literal at `pos`.
+    fn is_match_from(&self, hay: &[u8], pos: usize, budget: &mut Budget) -> Result<bool> {
+        let hay = &hay[pos + self.inner.needle().len()..];
+        let mut cache = self.cache.get(|| Cache::new(&self.forward));
+
+        match dfa::is_match(&self.forward, &mut cache, hay, None, budget)? {
+            Some(matched) => Ok(matched),
+            None => self.forward.is_match(hay, budget),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hir::translate;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    #[test]
+    fn it_matches_at_the_end() {
+        let patterns = translate(&parse_pattern("\\d+ (apple|orange)s?").patterns());
+        let rev = Reverse::new(&patterns, false);
+        let mut budget = Budget::unlimited();
+
+        let hay = b"I have 3 apples!";
+        assert!(rev.is_match_at(hay, 15, &mut budget).unwrap());
+        assert!(rev.is_match_at(hay, 14, &mut budget).unwrap());
+        assert!(!rev.is_match_at(hay, 16, &mut budget).unwrap());
+        assert!(!rev.is_match_at(hay, 13, &mut budget).unwrap());
+
+        let rev = Reverse::new(&patterns, true);
+        assert!(!rev.is_match_at(hay, 15, &mut budget).unwrap());
+        assert!(rev.is_match_at(b"3 apples", 8, &mut budget).unwrap());
+    }
+
+    #[test]
+    fn it_matches_backwards_from_suffixes() {
+        let patterns = translate(&parse_pattern("\\w+\\.log").patterns());
+        let rs = ReverseSuffix::new(&patterns, b".log");
+        let mut budget = Budget::unlimited();
+
+        assert_eq!(
+            rs.is_match(b"see app.log", &mut budget).unwrap(),
+            Some(true)
+        );
+        assert_eq!(rs.is_match(b"see .log", &mut budget).unwrap(), Some(false));
+        assert_eq!(
+            rs.is_match(b"see .log a.log", &mut budget).unwrap(),
+            Some(true)
+        );
+        assert_eq!(rs.is_match(b"app.txt", &mut budget).unwrap(), Some(false));
+    }
+
+    #[test]
+    fn it_gives_up_on_repeated_backward_searches() {
+        let patterns = translate(&parse_pattern("x\\w+a").patterns());
+        let rs = ReverseSuffix::new(&patterns, b"a");
+        let hay = "a".repeat(1000);
+
+        assert_eq!(
+            rs.is_match(hay.as_bytes(), &mut Budget::unlimited())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn it_matches_around_inner_literals() {
+        let patterns = translate(&parse_pattern("\\d+ ERROR \\w+").patterns());
+        let ri = ReverseInner::new(&patterns, b" ERROR ").unwrap();
+        let mut budget = Budget::unlimited();
+
+        assert_eq!(
+            ri.is_match(b"at 12 ERROR disk", &mut budget).unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            ri.is_match(b"x ERROR y 3 ERROR z", &mut budget).unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            ri.is_match(b"12 ERROR !", &mut budget).unwrap(),
+            Some(false)
+        );
+        assert_eq!(ri.is_match(b"x ERROR y", &mut budget).unwrap(), Some(false));
+        assert!(ReverseInner::new(&patterns, b"disk").is_none());
+    }
+}
@@ -0,0 +1,251 @@
+use super::budget::{Budget, Limits};
+use super::dfa::{self, Cache};
+use super::nfa::{Nfa, SparseSet, State, StateId};
+use super::pool::Pool;
+use super::prefilter::Prefilter;
+use super::{parse_expr, ParseError};
+use crate::Result;
+
+/// Several regexes compiled into a single NFA, which finds all of them that
+/// match a haystack in one pass over it.
+#[derive(Debug, Clone)]
+pub struct RegexSet {
+    nfa: Nfa,
+    starts: Vec<StateId>,
+    // The patterns without `^`, whose starts are entered again at every unit.
+    unanchored: Vec<usize>,
+    end_anchors: Vec<bool>,
+    // A regex is left out of the search of a haystack its prefilter rules out.
+    prefilters: Vec<Option<Prefilter>>,
+    cache: Pool<Cache>,
+    limits: Limits,
+}
+
+impl RegexSet {
+    /// Fails with the first problem found in the first invalid expression.
+    pub fn new<I, S>(exprs: I) -> std::result::Result<Self, ParseError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::with_limits(exprs, Limits::default())
+    }
+
+    pub(super) fn with_limits<I, S>(
+        exprs: I,
+        limits: Limits,
+    ) -> std::result::Result<Self, ParseError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let exprs: Vec<S> = exprs.into_iter().collect();
+        let mut patterns = vec![];
+        let mut start_anchors = vec![];
+        let mut end_anchors = vec![];
+        let mut prefilters = vec![];
+
+        for expr in &exprs {
+            let expr = parse_expr(expr.as_ref()).map_err(|errors| errors[0])?;
+            prefilters.push(Prefilter::new(
+                &expr.hir,
+                expr.start_anchor,
+                expr.end_anchor,
+            ));
+            patterns.push(expr.hir);
+            start_anchors.push(expr.start_anchor);
+            end_anchors.push(expr.end_anchor);
+        }
+
+        let (nfa, starts) = Nfa::union(&patterns);
+        let unanchored = (0..starts.len()).filter(|&i| !start_anchors[i]).collect();
+
+        Ok(Self {
+            nfa,
+            starts,
+            unanchored,
+            end_anchors,
+            prefilters,
+            cache: Pool::new(),
+            limits,
+        })
+    }
+
+    /// The number of regexes in the set.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// A search that runs out of the limits set with [`super::RegexSetBuilder`]
+    /// counts as no match, like in [`super::Regex::is_match`].
+    pub fn is_match(&self, s: &str) -> bool {
+        self.try_is_match(s).unwrap_or(false)
+    }
+
+    /// Like [`RegexSet::is_match`], but fails when the search exceeds the step
+    /// limit or the timeout.
+    pub fn try_is_match(&self, s: &str) -> Result<bool> {
+        self.try_is_match_bytes(s.as_bytes())
+    }
+
+    /// Returns the index of every regex matching `s`, in ascending order. A
+    /// search that runs out of its limits finds none.
+    pub fn matches(&self, s: &str) -> Vec<usize> {
+        self.try_matches(s).unwrap_or_default()
+    }
+
+    /// Like [`RegexSet::matches`], but fails when the search exceeds the step
+    /// limit or the timeout.
+    pub fn try_matches(&self, s: &str) -> Result<Vec<usize>> {
+        self.try_matches_bytes(s.as_bytes())
+    }
+
+    pub(super) fn try_is_match_bytes(&self, hay: &[u8]) -> Result<bool> {
+        let candidates = self.candidates(hay);
+
+        if !candidates.contains(&true) {
+            return Ok(false);
+        }
+
+        let mut budget = Budget::new(self.limits);
+
+        // Without anchors, the lazy DFA of the whole NFA tells whether any of
+        // the regexes matches, though not which.
+        if self.unanchored.len() == self.len() && !self.end_anchors.contains(&true) {
+            let mut cache = self.cache.get(|| Cache::new(&self.nfa));
+
+            if let Some(matched) = dfa::is_match(&self.nfa, &mut cache, hay, None, &mut budget)? {
+                return Ok(matched);
+            }
+        }
+
+        let matched = self.search(hay, &candidates, false, &mut budget)?;
+        Ok(!matched.is_empty())
+    }
+
+    pub(super) fn try_matches_bytes(&self, hay: &[u8]) -> Result<Vec<usize>> {
+        let candidates = self.candidates(hay);
+
+        if !candidates.contains(&true) {
+            return Ok(vec![]);
+        }
+
+        self.search(hay, &candidates, true, &mut Budget::new(self.limits))
+    }
+
+    // Whether each regex may match `hay` according to its prefilter.
+    fn candidates(&self, hay: &[u8]) -> Vec<bool> {
+        self.prefilters
+            .iter()
+            .map(|pre| pre.as_ref().map_or(true, |pre| pre.may_match(hay)))
+            .collect()
+    }
+
+    // Simulates the NFA of the `candidates`, stopping at the first regex that
+    // matches unless `all` is set. Advancing each active state by one unit
+    // costs a step of `budget`.
+    fn search(
+        &self,
+        hay: &[u8],
+        candidates: &[bool],
+        all: bool,
+        budget: &mut Budget,
+    ) -> Result<Vec<usize>> {
+        let mut cur = SparseSet::new(self.nfa.len());
+        let mut next = SparseSet::new(self.nfa.len());
+        let mut stack = vec![];
+        let mut matched = vec![false; self.len()];
+        let mut left = if all { self.len() } else { self.len().min(1) };
+        let mut pos = 0;
+
+        let unanchored: Vec<StateId> = self
+            .unanchored
+            .iter()
+            .filter(|&&i| candidates[i])
+            .map(|&i| self.starts[i])
+            .collect();
+
+        for i in (0..self.len()).filter(|&i| candidates[i]) {
+            self.nfa.add_closure(&mut cur, self.starts[i], &mut stack);
+        }
+
+        while left > 0 {
+            for id in cur.iter() {
+                if let State::Match(i) = *self.nfa.state(id) {
+                    if !matched[i] && left > 0 && (!self.end_anchors[i] || pos == hay.len()) {
+                        matched[i] = true;
+                        left -= 1;
+                    }
+                }
+            }
+
+            let Some((unit, len)) = self.nfa.next_unit(hay, pos) else {
+                break;
+            };
+
+            budget.spend(cur.len() as u64)?;
+
+            next.clear();
+            self.nfa.step(&cur, unit, &mut next, &mut stack);
+
+            for &start in &unanchored {
+                self.nfa.add_closure(&mut next, start, &mut stack);
+            }
+
+            if next.is_empty() {
+                break;
+            }
+
+            std::mem::swap(&mut cur, &mut next);
+            pos += len;
+        }
+
+        Ok((0..self.len()).filter(|&i| matched[i]).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Regex;
+
+    #[test]
+    fn it_reports_every_matching_regex() {
+        let set = RegexSet::new(["ERROR", "^\\d+ ", "disk$", "(cpu|mem)\\w*"]).unwrap();
+        assert_eq!(set.len(), 4);
+
+        assert_eq!(set.matches("12 ERROR disk"), [0, 1, 2]);
+        assert_eq!(set.matches("12 WARN memory"), [1, 3]);
+        assert_eq!(set.matches("x 12 disk!"), Vec::<usize>::new());
+        assert!(!set.is_match("nothing"));
+    }
+
+    #[test]
+    fn it_agrees_with_each_regex() {
+        for exprs in [
+            &["a+b", "^ab", "b$", "(?:ab|c)+d?", "[^a]"][..],
+            &["a+b", "cd", "(?:ab|c)+d?"],
+        ] {
+            let set = RegexSet::new(exprs).unwrap();
+
+            for hay in ["", "ab", "aab", "cab", "abcd", "b", "ba"] {
+                let expected: Vec<usize> = (0..exprs.len())
+                    .filter(|&i| Regex::new(exprs[i]).unwrap().is_match(hay))
+                    .collect();
+                assert_eq!(set.matches(hay), expected, "{hay:?}");
+                assert_eq!(set.is_match(hay), !expected.is_empty(), "{hay:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn it_matches_nothing_when_empty() {
+        let set = RegexSet::new(Vec::<&str>::new()).unwrap();
+        assert!(set.is_empty());
+        assert!(!set.is_match("abc"));
+    }
+}
@@ -0,0 +1,289 @@
+use super::budget::Budget;
+use super::charset::{Alphabet, CharSet};
+use super::hir::Hir;
+use super::utf8;
+use crate::Result;
+
+/// The most positions a pattern may have to fit into a machine word.
+const MAX_POSITIONS: usize = 64;
+
+/// A bit-parallel matcher over the Glushkov automaton of a pattern.
+///
+/// Every char-consuming pattern is a position with its own bit, so the set of
+/// active positions is a single `u64`. Reading a char takes the positions that
+/// may follow any active one, looked up a byte of the mask at a time, and keeps
+/// those accepting the char.
+#[derive(Debug, Clone)]
+pub struct ShiftAnd {
+    alphabet: Alphabet,
+    // Positions accepting each class of the alphabet.
+    masks: Vec<u64>,
+    // `follow[i][b]` is the union of the positions following the active ones
+    // when the `i`th byte of the state is `b`.
+    follow: Vec<[u64; 256]>,
+    first: u64,
+    last: u64,
+    nullable: bool,
+    start_anchor: bool,
+    end_anchor: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Info {
+    nullable: bool,
+    first: u64,
+    last: u64,
+}
+
+impl Info {
+    const EMPTY: Self = Self {
+        nullable: true,
+        first: 0,
+        last: 0,
+    };
+}
+
+struct Builder {
+    sets: Vec<CharSet>,
+    follow: Vec<u64>,
+}
+
+impl ShiftAnd {
+    /// Returns `None` if the pattern has more positions than fit into a word.
+    pub fn new(patterns: &[Hir], start_anchor: bool, end_anchor: bool) -> Option<Self> {
+        let mut builder = Builder {
+            sets: vec![],
+            follow: vec![],
+        };
+        let info = builder.concat(patterns)?;
+
+        let alphabet = Alphabet::new(builder.sets.iter());
+        let masks = (0..alphabet.len())
+            .map(|class| {
+                let c = alphabet.representative(class);
+                builder
+                    .sets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, set)| set.contains(c))
+                    .fold(0, |mask, (i, _)| mask | 1 << i)
+            })
+            .collect();
+
+        let follow = (0..8)
+            .map(|chunk| {
+                let mut table = [0u64; 256];
+
+                for (byte, entry) in table.iter_mut().enumerate() {
+                    for bit in 0..8 {
+                        if byte & (1 << bit) != 0 {
+                            *entry |= builder.follow.get(chunk * 8 + bit).copied().unwrap_or(0);
+                        }
+                    }
+                }
+
+                table
+            })
+            .collect();
+
+        Some(Self {
+            alphabet,
+            masks,
+            follow,
+            first: info.first,
+            last: info.last,
+            nullable: info.nullable,
+            start_anchor,
+            end_anchor,
+        })
+    }
+
+    fn follow(&self, state: u64) -> u64 {
+        state
+            .to_le_bytes()
+            .iter()
+            .zip(self.follow.iter())
+            .fold(0, |acc, (&byte, table)| acc | table[byte as usize])
+    }
+
+    /// Reading each unit of `hay` costs a step of `budget`.
+    pub fn is_match(&self, hay: &[u8], budget: &mut Budget) -> Result<bool> {
+        if self.nullable && (!self.start_anchor || !self.end_anchor || hay.is_empty()) {
+            return Ok(true);
+        }
+
+        let mut state = 0u64;
+        let mut pos = 0;
+
+        while let Some((c, len)) = utf8::decode(&hay[pos..]) {
+            budget.spend(1)?;
+
+            let mut reach = self.follow(state);
+
+            if !self.start_anchor || pos == 0 {
+                reach |= self.first;
+            }
+
+            state = reach & self.masks[self.alphabet.class_of(c)];
+            pos += len;
+
+            if !self.end_anchor && state & self.last != 0 {
+                return Ok(true);
+            }
+
+            if state == 0 && self.start_anchor {
+                return Ok(false);
+            }
+        }
+
+        Ok(state & self.last != 0)
+    }
+}
+
+impl Builder {
+    fn concat(&mut self, patterns: &[Hir]) -> Option<Info> {
+        patterns.iter().try_fold(Info::EMPTY, |acc, pat| {
+            let info = self.compile(pat)?;
+            self.link(acc.last, info.first);
+
+            Some(Info {
+                nullable: acc.nullable && info.nullable,
+                first: acc.first | if acc.nullable { info.first } else { 0 },
+                last: info.last | if info.nullable { acc.last } else { 0 },
+            })
+        })
+    }
+
+    fn compile(&mut self, pat: &Hir) -> Option<Info> {
+        match pat {
+            Hir::Literal(lit) => lit.chars().try_fold(Info::EMPTY, |acc, c| {
+                let info = self.position(CharSet::single(c))?;
+                self.link(acc.last, info.first);
+
+                Some(Info {
+                    nullable: false,
+                    first: if acc.nullable { info.first } else { acc.first },
+                    last: info.last,
+                })
+            }),
+            Hir::Class(set) => self.position(set.clone()),
+            Hir::Star(p) => {
+                let info = self.compile(p)?;
+                self.link(info.last, info.first);
+                Some(Info {
+                    nullable: true,
+                    ..info
+                })
+            }
+            Hir::Plus(p) => {
+                let info = self.compile(p)?;
+                self.link(info.last, info.first);
+                Some(info)
+            }
+            Hir::Optional(p) => {
+                let info = self.compile(p)?;
+                Some(Info {
+                    nullable: true,
+                    ..info
+                })
+            }
+            Hir::Capture { branches, .. } | Hir::Group(branches) => branches.iter().try_fold(
+                Info {
+                    nullable: false,
+                    first: 0,
+                    last: 0,
+                },
+                |acc, branch| {
+                    let info = self.concat(branch)?;
+                    Some(Info {
+                        nullable: acc.nullable || info.nullable,
+                        first: acc.first | info.first,
+                        last: acc.last | info.last,
+                    })
+                },
+            ),
+        }
+    }
+
+    fn position(&mut self, set: CharSet) -> Option<Info> {
+        if self.sets.len() == MAX_POSITIONS {
+            return None;
+        }
+
+        let bit = 1 << self.sets.len();
+        self.sets.push(set);
+        self.follow.push(0);
+
+        Some(Info {
+            nullable: false,
+            first: bit,
+            last: bit,
+        })
+    }
+
+    fn link(&mut self, from: u64, to: u64) {
+        for (i, follow) in self.follow.iter_mut().enumerate() {
+            if from & (1 << i) != 0 {
+                *follow |= to;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hir::translate;
+    use super::super::nfa::Nfa;
+    use super::super::pattern::parse_pattern;
+    use super::*;
+
+    fn assert_like_nfa(expr: &str, haystacks: &[&str]) {
+        let patterns = translate(&parse_pattern(expr).patterns());
+
+        for (start_anchor, end_anchor) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let sa = ShiftAnd::new(&patterns, start_anchor, end_anchor).unwrap();
+            let nfa = Nfa::new(&patterns, start_anchor, end_anchor);
+
+            for hay in haystacks {
+                let mut budget = Budget::unlimited();
+
+                assert_eq!(
+                    sa.is_match(hay.as_bytes(), &mut budget).unwrap(),
+                    nfa.is_match(hay.as_bytes(), &mut budget).unwrap(),
+                    "{expr} on {hay:?} with anchors {start_anchor} {end_anchor}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_matches_like_the_nfa() {
+        let haystacks = [
+            "", "a", "ab", "abc", "xabcx", "aab", "abab", "bcbc", "caaat",
+        ];
+
+        assert_like_nfa("abc", &haystacks);
+        assert_like_nfa("a+b", &haystacks);
+        assert_like_nfa("(ab)+", &haystacks);
+        assert_like_nfa("a*", &haystacks);
+        assert_like_nfa("c?a*t", &haystacks);
+        assert_like_nfa("(a|bc)*x?", &haystacks);
+        assert_like_nfa("(?:a|bc)*(?P<x>x)?", &haystacks);
+        assert_like_nfa("[^a]\\w", &haystacks);
+    }
+
+    #[test]
+    fn it_rejects_patterns_with_too_many_positions() {
+        let short = "a".repeat(MAX_POSITIONS);
+        let long = "a".repeat(MAX_POSITIONS + 1);
+
+        assert!(
+            ShiftAnd::new(&translate(&parse_pattern(&short).patterns()), false, false).is_some()
+        );
+        assert!(
+            ShiftAnd::new(&translate(&parse_pattern(&long).patterns()), false, false).is_none()
+        );
+    }
+}
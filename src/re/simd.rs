@@ -0,0 +1,198 @@
+//! Scanning for bytes in a few ranges many bytes at a time.
+
+/// Up to [`ByteClass::MAX_RANGES`] inclusive byte ranges, such as the bytes
+/// a match may start with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ByteClass {
+    // Sorted, non-overlapping and non-adjacent.
+    ranges: Vec<(u8, u8)>,
+}
+
+impl ByteClass {
+    /// The most ranges a class may have. Every range costs a few instructions
+    /// per block of bytes, so beyond that scanning stops paying off.
+    pub const MAX_RANGES: usize = 4;
+
+    /// Returns `None` if the ranges cover every byte or are too many.
+    pub fn new<I: IntoIterator<Item = (u8, u8)>>(ranges: I) -> Option<Self> {
+        let mut sorted: Vec<(u8, u8)> = ranges.into_iter().collect();
+        sorted.sort_unstable();
+
+        let mut ranges: Vec<(u8, u8)> = Vec::with_capacity(sorted.len());
+
+        for (lo, hi) in sorted {
+            match ranges.last_mut() {
+                Some(last) if lo as u16 <= last.1 as u16 + 1 => last.1 = last.1.max(hi),
+                _ => ranges.push((lo, hi)),
+            }
+        }
+
+        if ranges.is_empty() || ranges.len() > Self::MAX_RANGES || ranges == [(0, 0xFF)] {
+            return None;
+        }
+
+        Some(Self { ranges })
+    }
+
+    pub fn byte(b: u8) -> Self {
+        Self {
+            ranges: vec![(b, b)],
+        }
+    }
+
+    pub fn contains(&self, b: u8) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= b && b <= hi)
+    }
+
+    /// Finds the first byte of the class at or after `from`.
+    pub fn find(&self, hay: &[u8], from: usize) -> Option<usize> {
+        let rest = hay.get(from..)?;
+
+        #[cfg(target_arch = "x86_64")]
+        let found = x86::find(&self.ranges, rest);
+        #[cfg(not(target_arch = "x86_64"))]
+        let found = find_fallback(&self.ranges, rest);
+
+        found.map(|i| from + i)
+    }
+}
+
+fn find_fallback(ranges: &[(u8, u8)], hay: &[u8]) -> Option<usize> {
+    hay.iter()
+        .position(|&b| ranges.iter().any(|&(lo, hi)| lo <= b && b <= hi))
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{find_fallback, ByteClass};
+    use std::arch::x86_64::*;
+
+    pub fn find(ranges: &[(u8, u8)], hay: &[u8]) -> Option<usize> {
+        debug_assert!(ranges.len() <= ByteClass::MAX_RANGES);
+
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 is available, as just checked.
+            unsafe { find_avx2(ranges, hay) }
+        } else {
+            // SAFETY: SSE2 is part of the x86_64 baseline.
+            unsafe { find_sse2(ranges, hay) }
+        }
+    }
+
+    // A byte `b` is in `lo..=hi` exactly when `b - lo` wraps to at most
+    // `hi - lo`, and that unsigned comparison is `min(b - lo, hi - lo) == b - lo`.
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn find_sse2(ranges: &[(u8, u8)], hay: &[u8]) -> Option<usize> {
+        let mut los = [_mm_setzero_si128(); ByteClass::MAX_RANGES];
+        let mut widths = [_mm_setzero_si128(); ByteClass::MAX_RANGES];
+
+        for (i, &(lo, hi)) in ranges.iter().enumerate() {
+            los[i] = _mm_set1_epi8(lo as i8);
+            widths[i] = _mm_set1_epi8((hi - lo) as i8);
+        }
+
+        let mut i = 0;
+
+        while i + 16 <= hay.len() {
+            // SAFETY: the 16 bytes at `i` are within `hay`.
+            let block = _mm_loadu_si128(hay.as_ptr().add(i) as *const __m128i);
+            let mut found = _mm_setzero_si128();
+
+            for r in 0..ranges.len() {
+                let offset = _mm_sub_epi8(block, los[r]);
+                let inside = _mm_cmpeq_epi8(_mm_min_epu8(offset, widths[r]), offset);
+                found = _mm_or_si128(found, inside);
+            }
+
+            let mask = _mm_movemask_epi8(found);
+
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize);
+            }
+
+            i += 16;
+        }
+
+        find_fallback(ranges, &hay[i..]).map(|j| i + j)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn find_avx2(ranges: &[(u8, u8)], hay: &[u8]) -> Option<usize> {
+        let mut los = [_mm256_setzero_si256(); ByteClass::MAX_RANGES];
+        let mut widths = [_mm256_setzero_si256(); ByteClass::MAX_RANGES];
+
+        for (i, &(lo, hi)) in ranges.iter().enumerate() {
+            los[i] = _mm256_set1_epi8(lo as i8);
+            widths[i] = _mm256_set1_epi8((hi - lo) as i8);
+        }
+
+        let mut i = 0;
+
+        while i + 32 <= hay.len() {
+            // SAFETY: the 32 bytes at `i` are within `hay`.
+            let block = _mm256_loadu_si256(hay.as_ptr().add(i) as *const __m256i);
+            let mut found = _mm256_setzero_si256();
+
+            for r in 0..ranges.len() {
+                let offset = _mm256_sub_epi8(block, los[r]);
+                let inside = _mm256_cmpeq_epi8(_mm256_min_epu8(offset, widths[r]), offset);
+                found = _mm256_or_si256(found, inside);
+            }
+
+            let mask = _mm256_movemask_epi8(found);
+
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize);
+            }
+
+            i += 32;
+        }
+
+        find_sse2(ranges, &hay[i..]).map(|j| i + j)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(class: &ByteClass, hay: &[u8], from: usize) -> Option<usize> {
+        (from..hay.len()).find(|&i| class.contains(hay[i]))
+    }
+
+    #[test]
+    fn it_merges_and_limits_ranges() {
+        let class = ByteClass::new([(b'a', b'f'), (b'0', b'9'), (b'g', b'z')]).unwrap();
+        assert_eq!(class.ranges, [(b'0', b'9'), (b'a', b'z')]);
+
+        assert!(ByteClass::new([(0, 0x7F), (0x80, 0xFF)]).is_none());
+        assert!(ByteClass::new([(1, 1), (3, 3), (5, 5), (7, 7), (9, 9)]).is_none());
+        assert!(ByteClass::new([]).is_none());
+    }
+
+    #[test]
+    fn it_finds_bytes_like_a_plain_scan() {
+        let classes = [
+            ByteClass::byte(b'x'),
+            ByteClass::new([(b'0', b'9')]).unwrap(),
+            ByteClass::new([(0x80, 0xFF), (b'_', b'_')]).unwrap(),
+            ByteClass::new([(0, 0)]).unwrap(),
+        ];
+        let mut hay: Vec<u8> = (0..200u8).map(|i| b'a' + i % 20).collect();
+        hay[37] = b'x';
+        hay[101] = b'7';
+        hay[150] = 0xE2;
+        hay[199] = 0;
+
+        for class in &classes {
+            for from in [0, 1, 15, 16, 33, 38, 64, 100, 102, 151, 199, 200, 201] {
+                assert_eq!(
+                    class.find(&hay, from),
+                    naive(class, &hay, from),
+                    "{class:?} {from}"
+                );
+            }
+        }
+    }
+}
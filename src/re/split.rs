@@ -0,0 +1,112 @@
+use super::{FindMatches, Regex};
+
+impl Regex {
+    /// Returns the pieces of `s` between the matches of the regex. Matches at
+    /// either end yield an empty piece there.
+    pub fn split<'r, 'h>(&'r self, s: &'h str) -> Split<'r, 'h> {
+        Split {
+            matches: self.find_iter(s),
+            hay: s,
+            last: 0,
+            done: false,
+        }
+    }
+
+    /// Like [`Regex::split`], but yields at most `limit` pieces, the last of
+    /// which is the rest of `s` after `limit - 1` matches.
+    pub fn splitn<'r, 'h>(&'r self, s: &'h str, limit: usize) -> SplitN<'r, 'h> {
+        SplitN {
+            splits: self.split(s),
+            limit,
+        }
+    }
+}
+
+/// An iterator over the pieces between matches, see [`Regex::split`].
+#[derive(Debug)]
+pub struct Split<'r, 'h> {
+    matches: FindMatches<'r, 'h>,
+    hay: &'h str,
+    // Where the next piece starts.
+    last: usize,
+    done: bool,
+}
+
+impl<'h> Iterator for Split<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.matches.next() {
+            Some(m) => {
+                let piece = &self.hay[self.last..m.start];
+                self.last = m.end;
+                Some(piece)
+            }
+            None => {
+                self.done = true;
+                Some(&self.hay[self.last..])
+            }
+        }
+    }
+}
+
+/// An iterator over at most a given number of pieces, see [`Regex::splitn`].
+#[derive(Debug)]
+pub struct SplitN<'r, 'h> {
+    splits: Split<'r, 'h>,
+    limit: usize,
+}
+
+impl<'h> Iterator for SplitN<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.limit == 0 || self.splits.done {
+            return None;
+        }
+
+        self.limit -= 1;
+
+        if self.limit > 0 {
+            return self.splits.next();
+        }
+
+        self.splits.done = true;
+        Some(&self.splits.hay[self.splits.last..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_on_matches() {
+        let r = Regex::new("\\s*[,;]\\s*").unwrap();
+        let pieces: Vec<&str> = r.split("a , b;c ;; d").collect();
+        assert_eq!(pieces, ["a", "b", "c", "", "d"]);
+
+        let pieces: Vec<&str> = r.split(",a,").collect();
+        assert_eq!(pieces, ["", "a", ""]);
+
+        let pieces: Vec<&str> = r.split("").collect();
+        assert_eq!(pieces, [""]);
+    }
+
+    #[test]
+    fn it_splits_a_limited_number_of_times() {
+        let r = Regex::new(",").unwrap();
+        let pieces: Vec<&str> = r.splitn("a,b,c,d", 2).collect();
+        assert_eq!(pieces, ["a", "b,c,d"]);
+
+        let pieces: Vec<&str> = r.splitn("a,b", 5).collect();
+        assert_eq!(pieces, ["a", "b"]);
+
+        assert_eq!(r.splitn("a,b", 0).count(), 0);
+        assert_eq!(r.splitn("a,b", 1).collect::<Vec<_>>(), ["a,b"]);
+    }
+}
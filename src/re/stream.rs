@@ -0,0 +1,451 @@
+use super::charset::Unit;
+use super::nfa::{Nfa, SparseSet, State, StateId};
+use super::{utf8, MatchKind, Regex};
+use std::collections::VecDeque;
+
+/// A match found by a [`Stream`], as byte offsets from the start of the
+/// stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamMatch {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A matcher fed with input one chunk at a time, which finds matches spanning
+/// any number of chunks without keeping earlier chunks around.
+///
+/// It finds the same matches as [`Regex::find_iter`] over the whole input.
+/// A match is only reported once no preferred match can replace it, which may
+/// take input past its end, and that input is held until then so the search
+/// for the next match can resume where it ends. Patterns ending in `$` only
+/// match once the stream is finished.
+#[derive(Debug)]
+pub struct Stream<'r> {
+    nfa: &'r Nfa,
+    kind: MatchKind,
+    // The threads of the search in progress, in priority order.
+    cur: SparseSet,
+    next: SparseSet,
+    // Where the thread in each state started.
+    starts: Vec<u64>,
+    next_starts: Vec<u64>,
+    stack: Vec<StateId>,
+    // The bytes of a multi-byte sequence cut short at the end of a chunk.
+    pending: Vec<u8>,
+    pos: u64,
+    // The preferred match found so far by the search in progress.
+    best: Option<StreamMatch>,
+    // The units read since the end of `best`, searched again once it is final.
+    held: Vec<(Unit, usize)>,
+    // Whether the next unit is passed over, after an empty match.
+    skip: bool,
+    // The end of the last match reported.
+    last_end: Option<u64>,
+    // The matches found in the input fed by the latest call.
+    matches: Vec<StreamMatch>,
+}
+
+impl Regex {
+    /// Starts a [`Stream`] over input fed in chunks.
+    pub fn stream(&self) -> Stream<'_> {
+        Stream::new(&self.nfa, self.kind)
+    }
+}
+
+impl<'r> Stream<'r> {
+    fn new(nfa: &'r Nfa, kind: MatchKind) -> Self {
+        let mut stream = Self {
+            nfa,
+            kind,
+            cur: SparseSet::new(nfa.len()),
+            next: SparseSet::new(nfa.len()),
+            starts: vec![0; nfa.len()],
+            next_starts: vec![0; nfa.len()],
+            stack: vec![],
+            pending: vec![],
+            pos: 0,
+            best: None,
+            held: vec![],
+            skip: false,
+            last_end: None,
+            matches: vec![],
+        };
+        stream.reset();
+        stream
+    }
+
+    /// Forgets all input so far and starts over at offset 0.
+    pub fn reset(&mut self) {
+        self.cur.clear();
+        self.pending.clear();
+        self.pos = 0;
+        self.best = None;
+        self.held.clear();
+        self.skip = false;
+        self.last_end = None;
+        self.matches.clear();
+        self.arrive();
+    }
+
+    /// Feeds the next chunk of input. Returns the matches that became final
+    /// with it, which may have started and even ended in earlier chunks.
+    pub fn push(&mut self, chunk: &[u8]) -> &[StreamMatch] {
+        self.matches.clear();
+
+        let mut chunk = chunk;
+
+        if !self.pending.is_empty() {
+            // Complete the cut sequence with the first bytes of the chunk.
+            let held = self.pending.len();
+            let mut head = std::mem::take(&mut self.pending);
+            head.extend_from_slice(&chunk[..chunk.len().min(4 - held)]);
+
+            let used = self.feed(&head, false);
+
+            if used < held {
+                self.pending = head[used..].to_vec();
+                return &self.matches;
+            }
+
+            chunk = &chunk[used - held..];
+        }
+
+        let used = self.feed(chunk, false);
+        self.pending = chunk[used..].to_vec();
+        &self.matches
+    }
+
+    /// Ends the input, reading any bytes still held back as raw bytes and
+    /// accepting matches that must end at the end of the input. Returns the
+    /// matches found in doing so.
+    pub fn finish(&mut self) -> &[StreamMatch] {
+        self.matches.clear();
+
+        let pending = std::mem::take(&mut self.pending);
+        self.feed(&pending, true);
+        self.read(None);
+
+        &self.matches
+    }
+
+    /// Whether the input so far is known to contain a match, even one that is
+    /// not reported yet because a preferred one may still replace it.
+    pub fn is_match(&self) -> bool {
+        self.last_end.is_some() || self.best.is_some()
+    }
+
+    // Reads units from `bytes` and returns how many bytes were read. A
+    // sequence cut short at the end is left unread unless `last` is set.
+    fn feed(&mut self, bytes: &[u8], last: bool) -> usize {
+        let limit = if last {
+            bytes.len()
+        } else {
+            bytes.len() - utf8::incomplete_suffix(bytes)
+        };
+        let mut i = 0;
+
+        while i < limit {
+            let Some((unit, len)) = utf8::decode(&bytes[i..limit]) else {
+                break;
+            };
+
+            self.read(Some((unit, len)));
+            i += len;
+        }
+
+        i
+    }
+
+    // Reads the next unit, or the end of the input, and reports every match
+    // that becomes final. The units held after such a match are read again.
+    fn read(&mut self, input: Option<(Unit, usize)>) {
+        let mut todo = VecDeque::new();
+        let mut input = Some(input);
+
+        while let Some(unit) = input.take() {
+            let mut at_end = match unit {
+                Some(unit) => {
+                    self.advance(unit);
+                    false
+                }
+                None => {
+                    self.scan(true);
+                    true
+                }
+            };
+
+            while let Some(m) = self.best.filter(|_| self.cur.is_empty() || at_end) {
+                let held = std::mem::take(&mut self.held);
+                self.restart(m);
+
+                if at_end {
+                    todo.push_front(None);
+                    at_end = false;
+                }
+
+                for unit in held.into_iter().rev() {
+                    todo.push_front(Some(unit));
+                }
+            }
+
+            input = todo.pop_front();
+        }
+    }
+
+    // Reports a final match, unless it is an empty one right at the end of
+    // the previous one, and starts searching again where it ends. After an
+    // empty match, that is one unit further.
+    fn restart(&mut self, m: StreamMatch) {
+        if m.start < m.end || self.last_end != Some(m.end) {
+            self.matches.push(m);
+            self.last_end = Some(m.end);
+        }
+
+        self.best = None;
+        self.cur.clear();
+        self.pos = m.end;
+
+        if m.start == m.end {
+            self.skip = true;
+        } else {
+            self.arrive();
+        }
+    }
+
+    fn advance(&mut self, (unit, len): (Unit, usize)) {
+        if std::mem::take(&mut self.skip) {
+            self.pos += len as u64;
+            self.arrive();
+            return;
+        }
+
+        if self.best.is_some() {
+            self.held.push((unit, len));
+        }
+
+        self.next.clear();
+
+        for id in self.cur.iter() {
+            if let State::Char { set, next: to } = self.nfa.state(id) {
+                if set.contains(unit) {
+                    let start = self.starts[id];
+                    let added = self.next.len();
+                    self.nfa.add_closure(&mut self.next, *to, &mut self.stack);
+
+                    for id in self.next.iter().skip(added) {
+                        self.next_starts[id] = start;
+                    }
+                }
+            }
+        }
+
+        std::mem::swap(&mut self.cur, &mut self.next);
+        std::mem::swap(&mut self.starts, &mut self.next_starts);
+        self.pos += len as u64;
+        self.arrive();
+    }
+
+    // Starts a new thread at the current position while no match has been
+    // found yet, and looks for matches ending there.
+    fn arrive(&mut self) {
+        if self.best.is_none() && (!self.nfa.start_anchor() || self.pos == 0) {
+            let added = self.cur.len();
+            self.nfa
+                .add_closure(&mut self.cur, self.nfa.start(), &mut self.stack);
+
+            for id in self.cur.iter().skip(added) {
+                self.starts[id] = self.pos;
+            }
+        }
+
+        self.scan(false);
+    }
+
+    // Records the preferred match ending at the current position, like the
+    // PikeVM does, and keeps the threads that may still lead to a preferred
+    // one.
+    fn scan(&mut self, at_end: bool) {
+        self.next.clear();
+
+        for id in self.cur.iter() {
+            let start = self.starts[id];
+            let state = self.nfa.state(id);
+
+            if state.is_epsilon() || self.best.is_some_and(|m| start > m.start) {
+                continue;
+            }
+
+            if matches!(state, State::Match(_)) && (at_end || !self.nfa.end_anchor()) {
+                let replace = match (self.kind, self.best) {
+                    (MatchKind::LeftmostLongest, Some(m)) => start < m.start || self.pos > m.end,
+                    _ => true,
+                };
+
+                if replace {
+                    self.best = Some(StreamMatch {
+                        start,
+                        end: self.pos,
+                    });
+                    self.held.clear();
+                }
+
+                // Every thread after this one has a lower priority.
+                if self.kind == MatchKind::LeftmostFirst {
+                    break;
+                }
+
+                continue;
+            }
+
+            self.next.insert(id);
+            self.next_starts[id] = start;
+        }
+
+        std::mem::swap(&mut self.cur, &mut self.next);
+        std::mem::swap(&mut self.starts, &mut self.next_starts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::bytes;
+    use super::*;
+
+    fn matches(expr: &str, chunks: &[&[u8]]) -> Vec<(u64, u64)> {
+        let r = bytes::Regex::new(expr).unwrap();
+        let mut stream = r.stream();
+        let mut found = vec![];
+
+        for chunk in chunks {
+            found.extend(stream.push(chunk).iter().map(|m| (m.start, m.end)));
+        }
+
+        found.extend(stream.finish().iter().map(|m| (m.start, m.end)));
+        found
+    }
+
+    fn run(expr: &str, chunks: &[&[u8]]) -> Option<(u64, u64)> {
+        matches(expr, chunks).first().copied()
+    }
+
+    #[test]
+    fn it_matches_across_chunks() {
+        assert_eq!(
+            run("\\d+ apples", &[b"I have 1", b"2 app", b"les!"]),
+            Some((7, 16))
+        );
+        assert_eq!(run("apples", &[b"app", b"", b"l", b"es"]), Some((0, 6)));
+        assert_eq!(run("apples", &[b"app", b"le"]), None);
+    }
+
+    #[test]
+    fn it_keeps_reporting_matches() {
+        assert_eq!(
+            matches("ab", &[b"xa", b"bab", b"", b"a", b"b"]),
+            [(1, 3), (3, 5), (5, 7)]
+        );
+        assert_eq!(matches("a\\d", &[b"a1a", b"2a"]), [(0, 2), (2, 4)]);
+        assert_eq!(matches("x*", &[b"ab"]), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(matches("^a", &[b"aa"]), [(0, 1)]);
+        assert_eq!(matches("a$", &[b"aa", b"a"]), [(2, 3)]);
+    }
+
+    #[test]
+    fn it_finds_the_same_matches_as_find_iter() {
+        let cases = [
+            ("\\d+", "123 4 56"),
+            ("(a|ab)(c|bcd)", "abcd xabc"),
+            ("(ab|a)x?", "aab abx"),
+            ("a*", "baaé"),
+            ("\\d*", "12ab3"),
+            ("x*", ""),
+            ("^\\w+", "ab cd"),
+            ("\\w+$", "ab cd"),
+            ("(é+|a)", "aéé∈é"),
+        ];
+
+        for (expr, hay) in cases {
+            let r = Regex::new(expr).unwrap();
+            let expected: Vec<(u64, u64)> = r
+                .find_iter(hay)
+                .map(|m| (m.start as u64, m.end as u64))
+                .collect();
+
+            for size in [1, 2, 3, 64] {
+                let chunks: Vec<&[u8]> = hay.as_bytes().chunks(size).collect();
+                assert_eq!(matches(expr, &chunks), expected, "{expr} over {hay:?}");
+            }
+        }
+
+        let r = super::super::RegexBuilder::new("(a|ab)(c|bcd)?")
+            .match_kind(MatchKind::LeftmostLongest)
+            .build()
+            .unwrap();
+        let mut stream = r.stream();
+        assert_eq!(stream.push(b"abcd"), [StreamMatch { start: 0, end: 4 }]);
+    }
+
+    #[test]
+    fn it_knows_of_a_match_before_reporting_it() {
+        let r = Regex::new("\\d+").unwrap();
+        let mut stream = r.stream();
+
+        assert_eq!(stream.push(b"x12"), []);
+        assert!(stream.is_match());
+        assert_eq!(stream.push(b"3 "), [StreamMatch { start: 1, end: 4 }]);
+    }
+
+    #[test]
+    fn it_matches_a_huge_line_fed_in_small_chunks() {
+        let r = Regex::new("\"id\": ?\\d+\\}").unwrap();
+        let mut stream = r.stream();
+        let record = br#"{"name": "x", "id": 12345}, "#;
+        let mut found = vec![];
+
+        // 4 MiB, with a record straddling every chunk boundary.
+        for i in 0..(4 << 20) / 4096u64 {
+            let chunk = record
+                .iter()
+                .cycle()
+                .skip((i * 4096) as usize % record.len());
+            let chunk: Vec<u8> = chunk.take(4096).copied().collect();
+            found.extend_from_slice(stream.push(&chunk));
+        }
+
+        found.extend_from_slice(stream.finish());
+
+        let len = record.len() as u64;
+        assert_eq!(found.len() as u64, (4 << 20) / len);
+        assert!(found
+            .iter()
+            .enumerate()
+            .all(|(i, m)| m.start == i as u64 * len + 14 && m.end == m.start + 12));
+    }
+
+    #[test]
+    fn it_matches_chars_split_across_chunks() {
+        assert_eq!(run("a∈b", &[b"xa\xE2", b"\x88", b"\x88b"]), Some((1, 6)));
+        assert_eq!(run("a.b", &[b"a\xE2\x88", b"b"]), None);
+        assert_eq!(run("a\\xE2\\x88b", &[b"a\xE2\x88", b"b"]), Some((0, 4)));
+    }
+
+    #[test]
+    fn it_respects_anchors() {
+        assert_eq!(run("^ab", &[b"a", b"b"]), Some((0, 2)));
+        assert_eq!(run("^ab", &[b"xa", b"b"]), None);
+        assert_eq!(run("ab$", &[b"ab", b"ab"]), Some((2, 4)));
+        assert_eq!(run("ab$", &[b"ab", b"c"]), None);
+        assert_eq!(run("x*", &[]), Some((0, 0)));
+    }
+
+    #[test]
+    fn it_starts_over_after_a_reset() {
+        let r = Regex::new("ab").unwrap();
+        let mut stream = r.stream();
+
+        assert!(!stream.push(b"xab").is_empty());
+        stream.reset();
+        assert_eq!(stream.push(b"b"), []);
+        assert_eq!(stream.push(b"ab"), [StreamMatch { start: 1, end: 3 }]);
+    }
+}
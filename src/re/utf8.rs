@@ -0,0 +1,135 @@
+use super::charset::{Unit, RAW_BYTE};
+
+/// Decodes the first unit of `bytes` and returns it with its encoded length.
+///
+/// A byte that does not start a valid UTF-8 sequence is returned on its own as
+/// a raw byte unit, and decoding resumes right after it.
+pub fn decode(bytes: &[u8]) -> Option<(Unit, usize)> {
+    let first = *bytes.first()?;
+
+    if first < 0x80 {
+        return Some((first as Unit, 1));
+    }
+
+    let len = match first {
+        b if b >= 0xF0 => 4,
+        b if b >= 0xE0 => 3,
+        _ => 2,
+    };
+
+    let c = bytes
+        .get(..len)
+        .and_then(|seq| std::str::from_utf8(seq).ok())
+        .and_then(|s| s.chars().next());
+
+    match c {
+        Some(c) => Some((c as Unit, len)),
+        None => Some((RAW_BYTE + first as Unit, 1)),
+    }
+}
+
+/// Decodes the last unit of `bytes`, splitting them into the same units as
+/// decoding them from the start would.
+pub fn decode_last(bytes: &[u8]) -> Option<(Unit, usize)> {
+    let last = *bytes.last()?;
+
+    if last < 0x80 {
+        return Some((last as Unit, 1));
+    }
+
+    // A valid sequence is only ever followed by continuation bytes, so if one
+    // ends here, decoding from the start finds it too.
+    for len in 2..=bytes.len().min(4) {
+        if let Some((unit, n)) = decode(&bytes[bytes.len() - len..]) {
+            if n == len {
+                return Some((unit, len));
+            }
+        }
+    }
+
+    Some((RAW_BYTE + last as Unit, 1))
+}
+
+/// Returns how many bytes at the end of `bytes` start a multi-byte sequence
+/// that is cut short, and could still become valid with more bytes.
+pub fn incomplete_suffix(bytes: &[u8]) -> usize {
+    for len in 1..=bytes.len().min(3) {
+        let start = bytes.len() - len;
+
+        match bytes[start] {
+            // A continuation byte, so the sequence may start further back.
+            0x80..=0xBF => continue,
+            b @ 0xC0.. => {
+                let needed = match b {
+                    b if b >= 0xF0 => 4,
+                    b if b >= 0xE0 => 3,
+                    _ => 2,
+                };
+                return if needed > len { len } else { 0 };
+            }
+            _ => return 0,
+        }
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_chars() {
+        assert_eq!(decode(b"abc"), Some(('a' as Unit, 1)));
+        assert_eq!(decode("∈🌏".as_bytes()), Some(('∈' as Unit, 3)));
+        assert_eq!(decode("🌏".as_bytes()), Some(('🌏' as Unit, 4)));
+        assert_eq!(decode(b""), None);
+    }
+
+    #[test]
+    fn it_decodes_invalid_bytes_one_at_a_time() {
+        assert_eq!(decode(b"\xFFa"), Some((RAW_BYTE + 0xFF, 1)));
+        assert_eq!(decode(b"\x80"), Some((RAW_BYTE + 0x80, 1)));
+        assert_eq!(decode(b"\xE2\x88"), Some((RAW_BYTE + 0xE2, 1)));
+        assert_eq!(decode(b"\xF0\xC3\xA9"), Some((RAW_BYTE + 0xF0, 1)));
+        assert_eq!(decode(b"\xC3\xA9"), Some(('é' as Unit, 2)));
+    }
+
+    #[test]
+    fn it_finds_incomplete_sequences() {
+        assert_eq!(incomplete_suffix(b"abc"), 0);
+        assert_eq!(incomplete_suffix(b"a\xE2\x88"), 2);
+        assert_eq!(incomplete_suffix(b"a\xF0"), 1);
+        assert_eq!(incomplete_suffix("a∈".as_bytes()), 0);
+        assert_eq!(incomplete_suffix(b"\x88\x88\x88"), 0);
+        assert_eq!(incomplete_suffix(b""), 0);
+    }
+
+    #[test]
+    fn it_decodes_backwards_like_forwards() {
+        for bytes in [
+            "a∈🌏é".as_bytes(),
+            b"\xFFa\xE2\x88",
+            b"\xF0\xC3\xA9\x80",
+            b"\xE2\x88\x88\x88",
+            b"",
+        ] {
+            let mut forward = vec![];
+            let mut pos = 0;
+            while let Some((unit, len)) = decode(&bytes[pos..]) {
+                forward.push(unit);
+                pos += len;
+            }
+
+            let mut backward = vec![];
+            let mut end = bytes.len();
+            while let Some((unit, len)) = decode_last(&bytes[..end]) {
+                backward.push(unit);
+                end -= len;
+            }
+            backward.reverse();
+
+            assert_eq!(forward, backward, "{bytes:?}");
+        }
+    }
+}